@@ -0,0 +1,13 @@
+#![no_main]
+
+use gdlc::arc_parser::ArcArchive;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(archive) = ArcArchive::from_bytes(data.to_vec()) else {
+        return;
+    };
+    for i in 0..archive.entries().len() {
+        let _ = archive.extract_entry(i);
+    }
+});
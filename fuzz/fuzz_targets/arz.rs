@@ -0,0 +1,17 @@
+#![no_main]
+
+use gdlc::arz_parser::ArzArchive;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(archive) = ArzArchive::from_bytes(data.to_vec()) else {
+        return;
+    };
+    for record in archive.records() {
+        let _ = record.map(|record| record.to_dbr());
+    }
+    let names: Vec<String> = archive.record_names().map(str::to_string).collect();
+    for name in names {
+        let _ = archive.record(&name);
+    }
+});
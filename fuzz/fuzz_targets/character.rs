@@ -0,0 +1,8 @@
+#![no_main]
+
+use gdlc::player::CharacterItems;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = CharacterItems::from_bytes(data.to_vec());
+});
@@ -0,0 +1,36 @@
+#![no_main]
+
+use gdlc::decrypt::Decrypt;
+use libfuzzer_sys::fuzz_target;
+
+// Let the decrypted bytes pick which read comes next, so every kind of read gets exercised
+fuzz_target!(|data: &[u8]| {
+    let Ok(mut decrypt) = Decrypt::from_bytes(data.to_vec()) else {
+        return;
+    };
+    let mut blocks = Vec::new();
+    while let Ok(op) = decrypt.read_byte() {
+        let ok = match op % 8 {
+            0 => decrypt.read_int().is_ok(),
+            1 => decrypt.next_int().is_ok(),
+            2 => decrypt.read_bool().is_ok(),
+            3 => decrypt.read_str().is_ok(),
+            4 => decrypt.read_wide_string().is_ok(),
+            5 => decrypt.expect_int("fuzzed int", u32::from(op)).is_ok(),
+            6 => match decrypt.read_block_start(u32::from(op >> 3)) {
+                Ok(block) => {
+                    blocks.push(block);
+                    true
+                }
+                Err(_) => false,
+            },
+            _ => match blocks.pop() {
+                Some(block) => decrypt.read_block_end(&block).is_ok(),
+                None => true,
+            },
+        };
+        if !ok {
+            break;
+        }
+    }
+});
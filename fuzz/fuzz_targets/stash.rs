@@ -0,0 +1,8 @@
+#![no_main]
+
+use gdlc::stash::Stash;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = Stash::from_bytes(data.to_vec());
+});
@@ -1,7 +1,8 @@
 use std::collections::HashMap;
 use std::path::PathBuf;
-use std::io::Error;
-use super::ByteReader;
+use crate::byte_reader::ByteReader;
+use crate::byte_reader::lz4_size_is_plausible;
+use crate::error::GdError;
 
 #[derive(Debug, Clone)]
 #[allow(dead_code)]
@@ -19,19 +20,19 @@ pub struct ArcRecordHeader {
 }
 
 impl ArcRecordHeader {
-    pub fn new(byte_vec: &mut ByteReader) -> Self {
-        Self {
-            record_type: byte_vec.read_u32(),
-            offset: byte_vec.read_u32(),
-            len_compressed: byte_vec.read_u32(),
-            len_decompressed: byte_vec.read_u32(),
-            unknown: byte_vec.read_u32(),
-            filetime: byte_vec.read_u64(),
-            parts_count: byte_vec.read_u32(),
-            index: byte_vec.read_u32(),
-            str_len: byte_vec.read_u32(),
-            str_offset: byte_vec.read_u32(),
-        }
+    pub fn new(byte_vec: &mut ByteReader) -> Result<Self, GdError> {
+        Ok(Self {
+            record_type: byte_vec.read_u32()?,
+            offset: byte_vec.read_u32()?,
+            len_compressed: byte_vec.read_u32()?,
+            len_decompressed: byte_vec.read_u32()?,
+            unknown: byte_vec.read_u32()?,
+            filetime: byte_vec.read_u64()?,
+            parts_count: byte_vec.read_u32()?,
+            index: byte_vec.read_u32()?,
+            str_len: byte_vec.read_u32()?,
+            str_offset: byte_vec.read_u32()?,
+        })
     }
 }
 
@@ -48,16 +49,16 @@ pub struct ArcArchiveHeader {
 }
 
 impl ArcArchiveHeader {
-    fn new(byte_vec: &mut ByteReader) -> Self {
-        Self {
-            unknown: byte_vec.read_u32(),
-            version: byte_vec.read_u32(),
-            files_count: byte_vec.read_u32(),
-            records_count: byte_vec.read_u32(),
-            record_len: byte_vec.read_u32(),
-            string_table_len: byte_vec.read_u32(),
-            record_offset: byte_vec.read_u32(),
-        }
+    fn new(byte_vec: &mut ByteReader) -> Result<Self, GdError> {
+        Ok(Self {
+            unknown: byte_vec.read_u32()?,
+            version: byte_vec.read_u32()?,
+            files_count: byte_vec.read_u32()?,
+            records_count: byte_vec.read_u32()?,
+            record_len: byte_vec.read_u32()?,
+            string_table_len: byte_vec.read_u32()?,
+            record_offset: byte_vec.read_u32()?,
+        })
     }
 }
 
@@ -69,96 +70,278 @@ struct ArcRecordPartMetadata { // are these partial or whole records?
 }
 
 impl ArcRecordPartMetadata {
-    pub fn new(byte_vec: &mut ByteReader) -> Self {
-        Self {
-            offset: byte_vec.read_u32(),
-            len_compressed: byte_vec.read_u32(),
-            len_decompressed: byte_vec.read_u32(),
-        }
+    pub fn new(byte_vec: &mut ByteReader) -> Result<Self, GdError> {
+        Ok(Self {
+            offset: byte_vec.read_u32()?,
+            len_compressed: byte_vec.read_u32()?,
+            len_decompressed: byte_vec.read_u32()?,
+        })
+    }
+}
+
+/// A file stored in an ARC archive.
+#[derive(Debug, Clone)]
+pub struct ArcEntry {
+    pub name: String,
+    /// Windows FILETIME, ie. 100 nanosecond intervals since 1601-01-01 UTC.
+    pub filetime: u64,
+    pub len_compressed: u32,
+    pub len_decompressed: u32,
+    pub parts_count: u32,
+}
+
+impl ArcEntry {
+    /// The modification time as "YYYY-MM-DD hh:mm:ss" in UTC.
+    pub fn modified(&self) -> String {
+        format_filetime(self.filetime)
     }
 }
 
-pub fn read_archive(path: &PathBuf) -> Result<HashMap<String, String>, Error> {
-    let mut byte_vec = ByteReader::from_file(path)?;
-    let archive_header = ArcArchiveHeader::new(&mut byte_vec);
-    assert!(archive_header.version == 3, "expected header version 3, is {}", archive_header.version);
-
-    let record_headers = read_record_headers(&mut byte_vec, &archive_header);
-    let record_parts_metadata = read_record_metadata(&mut byte_vec, &archive_header);
-
-    let strings = read_strings(&mut byte_vec, &archive_header);
-    //let mut items_index = None;
-    let mut indices = Vec::new();
-    for (i, string) in strings.iter().enumerate() {
-        let file_names = [
-            "tags_items.txt",
-            "tagsgdx1_items.txt",
-            "tagsgdx2_items.txt",
-            "tagsgdx2_endlessdungeon.txt",
-            "tags_storyelements.txt",
-            "tagsgdx1_storyelements.txt",
-            "tagsgdx2_storyelements.txt",
-        ];
-        if file_names.iter().any(|s| string == s) {
-            indices.push(i);
+pub struct ArcArchive {
+    path: PathBuf,
+    reader: ByteReader,
+    record_headers: Vec<ArcRecordHeader>,
+    record_parts_metadata: Vec<ArcRecordPartMetadata>,
+    entries: Vec<ArcEntry>,
+}
+
+impl ArcArchive {
+    pub fn open(path: &PathBuf) -> Result<Self, GdError> {
+        Self::from_reader(path.clone(), ByteReader::from_file(path)?)
+    }
+
+    /// Read an archive that is already in memory.
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<Self, GdError> {
+        Self::from_reader(PathBuf::new(), ByteReader::from_vec(bytes))
+    }
+
+    fn from_reader(path: PathBuf, mut byte_vec: ByteReader) -> Result<Self, GdError> {
+        let archive_header = ArcArchiveHeader::new(&mut byte_vec)?;
+        if archive_header.version != 3 {
+            byte_vec.index = 4;
+            return Err(byte_vec.unexpected_value("archive version", 3, archive_header.version));
+        }
+
+        let record_headers = read_record_headers(&mut byte_vec, &archive_header)?;
+        let record_parts_metadata = read_record_metadata(&mut byte_vec, &archive_header)?;
+        let string_table = read_string_table(&mut byte_vec, &archive_header)?;
+
+        let mut entries = Vec::with_capacity(record_headers.len());
+        for header in &record_headers {
+            entries.push(ArcEntry {
+                name: entry_name(&byte_vec, &string_table, header)?,
+                filetime: header.filetime,
+                len_compressed: header.len_compressed,
+                len_decompressed: header.len_decompressed,
+                parts_count: header.parts_count,
+            });
+        }
+
+        Ok(Self {
+            path,
+            reader: byte_vec,
+            record_headers,
+            record_parts_metadata,
+            entries,
+        })
+    }
+
+    pub fn path(&self) -> &PathBuf {
+        &self.path
+    }
+
+    pub fn entries(&self) -> &[ArcEntry] {
+        &self.entries
+    }
+
+    /// Extract a file by its name, eg. "tags_items.txt". Case and slash direction are ignored.
+    pub fn extract(&self, name: &str) -> Result<Option<Vec<u8>>, GdError> {
+        let name = normalize_entry_name(name);
+        match self.entries.iter().position(|entry| normalize_entry_name(&entry.name) == name) {
+            Some(i) => Ok(Some(self.extract_entry(i)?)),
+            None => Ok(None),
         }
     }
-    assert_eq!(archive_header.files_count as usize, record_headers.len());
+
+    /// Extract the file at the given index of entries().
+    pub fn extract_entry(&self, i: usize) -> Result<Vec<u8>, GdError> {
+        let header = &self.record_headers[i];
+        let mut reader = self.reader.clone();
+        // Type 1 entries are stored as is, everything else is split into (possibly compressed) parts
+        if header.record_type == 1 && header.len_compressed == header.len_decompressed {
+            reader.index = header.offset as usize;
+            return reader.read_n_bytes(header.len_compressed);
+        }
+        let first_part = header.index as usize;
+        let last_part = first_part + header.parts_count as usize;
+        let Some(parts) = self.record_parts_metadata.get(first_part..last_part) else {
+            return Err(reader.unexpected_value(
+                "file parts",
+                format!("at most {}", self.record_parts_metadata.len()),
+                format!("{first_part}..{last_part}"),
+            ));
+        };
+        let mut data = Vec::new();
+        for part in parts {
+            data.append(&mut decompress(&mut reader, part)?);
+        }
+        Ok(data)
+    }
+}
+
+/// Read the item and story element names from a localization archive, eg. Text_EN.arc.
+pub fn read_archive(path: &PathBuf) -> Result<HashMap<String, String>, GdError> {
+    let file_names = [
+        "tags_items.txt",
+        "tagsgdx1_items.txt",
+        "tagsgdx2_items.txt",
+        "tagsgdx2_endlessdungeon.txt",
+        "tags_storyelements.txt",
+        "tagsgdx1_storyelements.txt",
+        "tagsgdx2_storyelements.txt",
+    ];
+    read_tags(&ArcArchive::open(path)?, &file_names, |_| true)
+}
+
+/// Localized class names by class tag, eg. "tagSkillClassName01" is "Soldier". Dual classes have both class
+/// numbers in the tag, eg. "tagSkillClassName0104".
+pub fn read_class_names(path: &PathBuf) -> Result<HashMap<String, String>, GdError> {
+    let file_names = [
+        "tags_ui.txt",
+        "tagsgdx1_ui.txt",
+        "tagsgdx2_ui.txt",
+        "tags_skills.txt",
+        "tagsgdx1_skills.txt",
+        "tagsgdx2_skills.txt",
+    ];
+    read_tags(&ArcArchive::open(path)?, &file_names, |tag| tag.starts_with("tagSkillClassName"))
+}
+
+/// Read "tag=text" lines from the given text files of a localization archive. Files the archive doesn't have are
+/// skipped, the expansions only have some of them.
+fn read_tags(
+    archive: &ArcArchive,
+    file_names: &[&str],
+    keep: impl Fn(&str) -> bool,
+) -> Result<HashMap<String, String>, GdError> {
     let mut map = HashMap::new();
-    for i in indices {
-        let data = decompress(&mut byte_vec, &record_parts_metadata[i]);
-        for string in String::from_utf8(data).unwrap().lines() {
-            if string.is_empty() || string.starts_with("#") {
-                continue
-            }
-            if let Some((key, value)) = string.split_once('=') {
-                map.insert(key.to_string(), value.to_string());
+    for file_name in file_names {
+        if let Some(data) = archive.extract(file_name)? {
+            for string in String::from_utf8_lossy(&data).lines() {
+                if string.is_empty() || string.starts_with("#") {
+                    continue
+                }
+                if let Some((key, value)) = string.split_once('=')
+                    && keep(key)
+                {
+                    map.insert(key.to_string(), value.to_string());
+                }
             }
         }
     }
     Ok(map)
 }
 
-fn read_record_metadata(byte_vec: &mut ByteReader, header: &ArcArchiveHeader) -> Vec<ArcRecordPartMetadata> {
-        let mut record_metadatas: Vec<ArcRecordPartMetadata> = Vec::with_capacity(header.records_count as usize);
+fn normalize_entry_name(name: &str) -> String {
+    name.replace('\\', "/").to_lowercase()
+}
+
+fn entry_name(byte_vec: &ByteReader, string_table: &[u8], header: &ArcRecordHeader) -> Result<String, GdError> {
+    let start = header.str_offset as usize;
+    let end = start + header.str_len as usize;
+    match string_table.get(start..end) {
+        Some(name) => Ok(String::from_utf8_lossy(name).to_string()),
+        None => Err(byte_vec.unexpected_value(
+            "file name position",
+            format!("within {} bytes", string_table.len()),
+            format!("{start}..{end}"),
+        )),
+    }
+}
+
+/// Convert a Windows FILETIME to "YYYY-MM-DD hh:mm:ss" UTC.
+pub fn format_filetime(filetime: u64) -> String {
+    // Seconds between 1601-01-01 and 1970-01-01
+    const EPOCH_DIFFERENCE: i64 = 11_644_473_600;
+    let unix_secs = (filetime / 10_000_000) as i64 - EPOCH_DIFFERENCE;
+    let days = unix_secs.div_euclid(86_400);
+    let secs_of_day = unix_secs.rem_euclid(86_400);
+
+    // Civil date from days since the unix epoch, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02}:{:02}",
+        secs_of_day / 3600,
+        secs_of_day % 3600 / 60,
+        secs_of_day % 60
+    )
+}
+
+fn read_record_metadata(
+    byte_vec: &mut ByteReader,
+    header: &ArcArchiveHeader,
+) -> Result<Vec<ArcRecordPartMetadata>, GdError> {
+        let mut record_metadatas: Vec<ArcRecordPartMetadata> =
+            Vec::with_capacity(byte_vec.capacity_for(header.records_count, 12));
         byte_vec.index = header.record_offset as usize;
         for _ in 0..header.records_count {
-            record_metadatas.push(ArcRecordPartMetadata::new(byte_vec));
+            record_metadatas.push(ArcRecordPartMetadata::new(byte_vec)?);
         }
-        record_metadatas
+        Ok(record_metadatas)
 }
 
-fn read_strings(byte_vec: &mut ByteReader, header: &ArcArchiveHeader) -> Vec<String> {
-    let mut strings = Vec::new();
-    byte_vec.index = (header.record_offset + header.record_len) as usize;
-
-    for _ in 0..header.files_count {
-        let string = byte_vec.read_null_string().unwrap();
-        strings.push(string);
-    }
-    strings
+fn read_string_table(byte_vec: &mut ByteReader, header: &ArcArchiveHeader) -> Result<Vec<u8>, GdError> {
+    byte_vec.index = header.record_offset as usize + header.record_len as usize;
+    byte_vec.read_n_bytes(header.string_table_len)
 }
 
-fn read_record_headers(byte_vec: &mut ByteReader, header: &ArcArchiveHeader) -> Vec<ArcRecordHeader> {
+fn read_record_headers(byte_vec: &mut ByteReader, header: &ArcArchiveHeader) -> Result<Vec<ArcRecordHeader>, GdError> {
     let mut records = Vec::new();
-    byte_vec.index = (header.record_offset + header.record_len + header.string_table_len) as usize;
+    byte_vec.index = header.record_offset as usize + header.record_len as usize + header.string_table_len as usize;
     for _ in 0..header.files_count {
-        records.push(ArcRecordHeader::new(byte_vec));
+        records.push(ArcRecordHeader::new(byte_vec)?);
     }
-    records
+    Ok(records)
 }
 
-fn decompress(byte_vec: &mut ByteReader, metadata: &ArcRecordPartMetadata) -> Vec<u8> {
+fn decompress(byte_vec: &mut ByteReader, metadata: &ArcRecordPartMetadata) -> Result<Vec<u8>, GdError> {
     let mut data: Vec<u8> = Vec::new();
     byte_vec.index = metadata.offset as usize;
     if metadata.len_compressed == metadata.len_decompressed {
-        data.append(&mut byte_vec.read_n_bytes(metadata.len_compressed).to_vec());
+        data.append(&mut byte_vec.read_n_bytes(metadata.len_compressed)?);
     } else {
+        let compressed_data = &byte_vec.read_n_bytes(metadata.len_compressed)?;
+        if !lz4_size_is_plausible(metadata.len_compressed, metadata.len_decompressed) {
+            return Err(GdError::Decompress {
+                path: byte_vec.path().clone(),
+                offset: metadata.offset as usize,
+                reason: format!("{} bytes can't decompress to {}", metadata.len_compressed, metadata.len_decompressed),
+            });
+        }
+        // lz4 takes the size as an i32
+        let len_decompressed = metadata.len_decompressed.try_into().map_err(|_| GdError::Decompress {
+            path: byte_vec.path().clone(),
+            offset: metadata.offset as usize,
+            reason: format!("{} bytes is too big to decompress to", metadata.len_decompressed),
+        })?;
         let mut buf = vec![0; metadata.len_decompressed as usize];
-        let compressed_data = &byte_vec.read_n_bytes(metadata.len_compressed);
-        lz4::block::decompress_to_buffer(compressed_data, Some(metadata.len_decompressed.try_into().unwrap()), &mut buf).unwrap();
+        lz4::block::decompress_to_buffer(compressed_data, Some(len_decompressed), &mut buf).map_err(|e| {
+            GdError::Decompress {
+                path: byte_vec.path().clone(),
+                offset: metadata.offset as usize,
+                reason: e.to_string(),
+            }
+        })?;
         data.append(&mut buf.to_vec());
     }
-    data
+    Ok(data)
 }
-
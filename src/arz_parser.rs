@@ -1,10 +1,12 @@
 use crate::byte_reader::ByteReader;
+use crate::byte_reader::lz4_size_is_plausible;
+use crate::error::GdError;
+use crate::item_category::ItemCategory;
 use std::collections::HashMap;
-use std::io::Error;
+use std::fs;
+use std::path::Path;
 use std::path::PathBuf;
 use std::sync::Arc;
-use std::sync::mpsc;
-use std::thread;
 
 #[derive(Clone, Debug)]
 pub struct ArzRecordHeader {
@@ -16,17 +18,17 @@ pub struct ArzRecordHeader {
 }
 
 impl ArzRecordHeader {
-    fn read(byte_vec: &mut ByteReader) -> Self {
-        let string_index = byte_vec.read_u32();
-        let str_len = byte_vec.read_u32();
-        let record_type = byte_vec.read_string(str_len);
-        Self {
+    fn read(byte_vec: &mut ByteReader) -> Result<Self, GdError> {
+        let string_index = byte_vec.read_u32()?;
+        let str_len = byte_vec.read_u32()?;
+        let record_type = byte_vec.read_string(str_len)?;
+        Ok(Self {
             string_index,
             record_type,
-            offset: byte_vec.read_u32(),
-            size_compressed: byte_vec.read_u32(),
-            size_decompressed: byte_vec.read_u32(),
-        }
+            offset: byte_vec.read_u32()?,
+            size_compressed: byte_vec.read_u32()?,
+            size_decompressed: byte_vec.read_u32()?,
+        })
     }
 }
 
@@ -43,16 +45,16 @@ struct ArzArchiveHeader {
 }
 
 impl ArzArchiveHeader {
-    fn new(byte_vec: &mut ByteReader) -> Self {
-        Self {
-            unknown: byte_vec.read_u16(),
-            version: byte_vec.read_u16(),
-            records_start: byte_vec.read_u32(),
-            records_len: byte_vec.read_u32(),
-            records_count: byte_vec.read_u32(),
-            strings_start: byte_vec.read_u32(),
-            strings_size: byte_vec.read_u32(),
-        }
+    fn new(byte_vec: &mut ByteReader) -> Result<Self, GdError> {
+        Ok(Self {
+            unknown: byte_vec.read_u16()?,
+            version: byte_vec.read_u16()?,
+            records_start: byte_vec.read_u32()?,
+            records_len: byte_vec.read_u32()?,
+            records_count: byte_vec.read_u32()?,
+            strings_start: byte_vec.read_u32()?,
+            strings_size: byte_vec.read_u32()?,
+        })
     }
 }
 
@@ -64,158 +66,343 @@ struct EntryHeader {
 }
 
 impl EntryHeader {
-    fn read(byte_vec: &mut ByteReader) -> Self {
-        Self {
-            entry_type: byte_vec.read_u16(),
-            entry_count: byte_vec.read_u16(),
-            string_index: byte_vec.read_u32(),
-        }
+    fn read(byte_vec: &mut ByteReader) -> Result<Self, GdError> {
+        Ok(Self {
+            entry_type: byte_vec.read_u16()?,
+            entry_count: byte_vec.read_u16()?,
+            string_index: byte_vec.read_u32()?,
+        })
     }
 }
 
-type Items = HashMap<String, (EntryType, Vec<Option<u32>>)>;
-type Affixes = HashMap<String, EntryType>;
-
-pub fn read_archive(path: &PathBuf) -> Result<(Items, Affixes), Error> {
-    let mut reader = ByteReader::from_file(path)?;
-
-    let archive_header = ArzArchiveHeader::new(&mut reader);
-
-    // Asserts copied from Item Assistant example
-    assert_eq!(archive_header.unknown, 2);
-    assert_eq!(archive_header.version, 3);
-
-    let strings = Arc::new(read_strings(&mut reader, &archive_header));
-    let record_headers = read_record_headers(&mut reader, &archive_header);
-
-    let (tx, rx) = mpsc::channel();
-    let mut threads = 0;
-    let mut thread_names = Vec::new();
-
-    'header_loop: for record_header in record_headers {
-        let record_name = strings[record_header.string_index as usize].clone();
-        // Uncomment to debug why something is not getting properly read
-        // note for debugging: record_type.is_empty() also yields values
-        //let catch = "records/items/crafting/blueprints/other/craft_potion_royaljellyointment.dbr";
-        //if record_name == catch {
-        //    println!("{record_name}: {:?}", record_header.record_type);
-        //}
-
-        if record_header.record_type.starts_with("Armor")
-            || record_header.record_type.starts_with("Item")
-            || record_header.record_type.starts_with("QuestItem")
-            || record_header.record_type.starts_with("Weapon")
-            || record_header.record_type.starts_with("OneShot_Scroll")
-            // starts_with() would also match "LootRandomizerTable"
-            || record_header.record_type == "LootRandomizer"
-        {
-            if record_header.record_type.starts_with("Item") {
-                let ignore_list = [
-                    "ItemTransmuter",
-                    "ItemTransmuterSet",
-                    "ItemSetFormula",
-                    "ItemRandomSetFormula",
-                ];
-                for ign in ignore_list {
-                    if record_header.record_type.starts_with(ign) {
-                        continue 'header_loop;
-                    }
-                }
+/// An opened ARZ archive. Only the string table and record headers are read up front, records are decompressed
+/// when they are asked for.
+pub struct ArzArchive {
+    path: PathBuf,
+    reader: ByteReader,
+    strings: Arc<Vec<String>>,
+    record_headers: Vec<ArzRecordHeader>,
+    index: HashMap<String, usize>,
+}
 
-                //println!("{}", record_header.record_type);
-            }
-            if record_name.starts_with("records/items/")
-                || record_name.starts_with("records/creatures/npcs/npcgear/")
-                || record_name.starts_with("records/storyelements/")
-                || record_name.starts_with("records/endlessdungeon/")
-            {
-                //println!("record type {}", record_header.record_type);
-                let ignore_list = [
-                    "records/items/enemygear/",
-                    "records/items/transmutes/",
-                    // Searching for unique affixes. Maybe later.
-                    "records/items/lootaffixes/prefixunique/",
-                    "records/items/lootaffixes/suffixunique/",
-                    "records/items/lootaffixes/completionrelics",
-                    "records/items/lootaffixes/completion",
-                    "records/items/lootaffixes/crafting",
-                ];
-                for ign in ignore_list {
-                    if record_name.starts_with(ign) {
-                        continue 'header_loop;
-                    }
-                }
+impl ArzArchive {
+    pub fn open(path: &PathBuf) -> Result<Self, GdError> {
+        Self::from_reader(path.clone(), ByteReader::from_file(path)?)
+    }
 
-                threads += 1;
-                thread_names.push(record_name.clone());
-                let strings = strings.clone();
-                let mut reader = reader.clone();
-
-                let tx = tx.clone();
-                // TODO this spawns needlessly many threads
-                thread::spawn(move || {
-                    let data = decompress(&mut reader, &record_header);
-                    let is_affix = record_header.record_type == "LootRandomizer";
-                    let entry = parse_record(&record_header, data, &record_name, &strings, is_affix);
-                    tx.send(Some((record_name, entry, is_affix))).unwrap();
-                });
-            }
+    /// Read an archive that is already in memory.
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<Self, GdError> {
+        Self::from_reader(PathBuf::new(), ByteReader::from_vec(bytes))
+    }
+
+    fn from_reader(path: PathBuf, mut reader: ByteReader) -> Result<Self, GdError> {
+        let archive_header = ArzArchiveHeader::new(&mut reader)?;
+
+        // Checks copied from Item Assistant example
+        if archive_header.unknown != 2 {
+            reader.index = 0;
+            return Err(reader.unexpected_value("archive magic", 2, archive_header.unknown));
+        }
+        if archive_header.version != 3 {
+            reader.index = 2;
+            return Err(reader.unexpected_value("archive version", 3, archive_header.version));
+        }
+
+        let strings = Arc::new(read_strings(&mut reader, &archive_header)?);
+        let record_headers = read_record_headers(&mut reader, &archive_header)?;
+        // Checked once here so that record names can be indexed directly afterwards
+        for header in &record_headers {
+            string_at(&strings, &reader, header.string_index)?;
         }
+        let index = record_headers
+            .iter()
+            .enumerate()
+            .map(|(i, header)| (normalize_record_name(&strings[header.string_index as usize]), i))
+            .collect();
+
+        Ok(Self {
+            path,
+            reader,
+            strings,
+            record_headers,
+            index,
+        })
     }
 
-    let mut items = Items::new();
-    let mut affixes = Affixes::new();
-
-    #[allow(clippy::needless_range_loop)]
-    for i in 0..threads {
-        match rx.recv() {
-            Ok(msg) => {
-                if let Some((record_name, entry, is_affix)) = msg {
-                    match entry {
-                        Some(e) => {
-                            if is_affix {
-                                affixes.insert(record_name, e);
-                            } else if let EntryType::Item(.., req) = e {
-                                if let Some((entry, ilvls)) = items.get_mut(&record_name) {
-                                    println!("doing the thing for {:?}", entry);
-                                    ilvls.push(req);
-                                } else {
-                                    items.insert(record_name, (e, Vec::new()));
-                                }
-                            } else {
-                                unreachable!("e is EntryType::Item if is_affix is false.");
-                            }
-                        }
-                        None => {
-                            println!("nothing found for {record_name}");
-                        }
-                    }
+    pub fn path(&self) -> &PathBuf {
+        &self.path
+    }
+
+    pub fn len(&self) -> usize {
+        self.record_headers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.record_headers.is_empty()
+    }
+
+    /// Record names in the order they are stored in the archive.
+    pub fn record_names(&self) -> impl Iterator<Item = &str> {
+        self.record_headers.iter().map(|header| self.strings[header.string_index as usize].as_str())
+    }
+
+    pub fn contains(&self, record_name: &str) -> bool {
+        self.index.contains_key(&normalize_record_name(record_name))
+    }
+
+    /// The records tag_entry() finds an item in, without decoding them. Affixes are left out.
+    pub fn item_record_names(&self) -> impl Iterator<Item = &str> {
+        self.record_headers
+            .iter()
+            .filter(|header| header.record_type != "LootRandomizer")
+            .map(|header| (self.strings[header.string_index as usize].as_str(), &header.record_type))
+            .filter(|(name, record_type)| is_tag_record(name, record_type))
+            .map(|(name, _)| name)
+    }
+
+    /// Look up a record by its path, eg. "records/items/gearrelic/a01_relic.dbr". Backslashes and case are ignored.
+    pub fn record(&self, record_name: &str) -> Result<Option<DbRecord>, GdError> {
+        match self.index.get(&normalize_record_name(record_name)) {
+            Some(&i) => Ok(Some(self.read_record(&self.record_headers[i])?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Decode every record of the archive.
+    pub fn records(&self) -> impl Iterator<Item = Result<DbRecord, GdError>> + '_ {
+        self.record_headers.iter().map(|header| self.read_record(header))
+    }
+
+    /// Write every record as a .dbr text file under "output_dir/<archive name>/", eg. "dump/database/records/...".
+    /// Returns the number of records written, and why the others were skipped.
+    pub fn dump(&self, output_dir: &Path) -> Result<(usize, Vec<String>), GdError> {
+        let archive_name = self.path.file_stem().unwrap_or_default();
+        let archive_dir = output_dir.join(archive_name);
+        let mut count = 0;
+        let mut skipped = Vec::new();
+        for record in self.records() {
+            let record = match record {
+                Ok(record) => record,
+                Err(e) => {
+                    skipped.push(e.to_string());
+                    continue;
                 }
+            };
+            let relative_path = normalize_record_name(&record.name);
+            // Record names come from the archive, don't let them escape the output directory
+            if relative_path.split('/').any(|part| part.is_empty() || part == "." || part == "..") {
+                skipped.push(format!("Skipping record with invalid name {:?}", record.name));
+                continue;
             }
-            Err(e) => {
-                println!("recv for {} failed with err {e}", &thread_names[i]);
+            let path = archive_dir.join(relative_path);
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent).map_err(|e| GdError::io(parent, e))?;
             }
+            fs::write(&path, record.to_dbr()).map_err(|e| GdError::io(&path, e))?;
+            count += 1;
+        }
+        Ok((count, skipped))
+    }
+
+    /// The name and rarity of an item or affix record, for looking up what's in a save. None if the archive doesn't
+    /// have the record, or it isn't an item or affix. Only this one record gets decompressed.
+    pub fn tag_entry(&self, record_name: &str) -> Result<Option<EntryType>, GdError> {
+        let Some(&i) = self.index.get(&normalize_record_name(record_name)) else {
+            return Ok(None);
+        };
+        let header = &self.record_headers[i];
+        let name = &self.strings[header.string_index as usize];
+        if !is_tag_record(name, &header.record_type) {
+            return Ok(None);
         }
+        let is_affix = header.record_type == "LootRandomizer";
+        decompress(&mut self.reader.clone(), header)
+            .and_then(|data| parse_record(header, data, name, &self.strings, is_affix))
+            .map_err(|e| GdError::Record {
+                record_name: name.clone(),
+                source: Box::new(e.with_path(&self.path)),
+            })
+    }
+
+    fn read_record(&self, header: &ArzRecordHeader) -> Result<DbRecord, GdError> {
+        let name = self.strings[header.string_index as usize].clone();
+        let mut reader = self.reader.clone();
+        let fields = decompress(&mut reader, header)
+            .and_then(|data| read_fields(header, data, &self.strings))
+            .map_err(|e| GdError::Record {
+                record_name: name.clone(),
+                source: Box::new(e.with_path(&self.path)),
+            })?;
+        Ok(DbRecord {
+            name,
+            record_type: header.record_type.clone(),
+            fields,
+        })
     }
-    Ok((items, affixes))
 }
 
-// Used by the logic in parse_record(). Knowing the type of the record could be important later.
-#[derive(Debug)]
-#[allow(dead_code)]
-enum EntryValue {
-    Float(f32),
-    Text(String),
-    Int(u32),
+fn normalize_record_name(record_name: &str) -> String {
+    record_name.replace('\\', "/").to_lowercase()
 }
 
-#[derive(Debug)]
+/// Whether a record is an item or affix that saves can refer to, and that has a name worth looking up.
+fn is_tag_record(record_name: &str, record_type: &str) -> bool {
+    let wanted_type = record_type.starts_with("Armor")
+        || record_type.starts_with("Item")
+        || record_type.starts_with("QuestItem")
+        || record_type.starts_with("Weapon")
+        // Potions, scrolls and other consumables
+        || record_type.starts_with("OneShot")
+        // starts_with() would also match "LootRandomizerTable"
+        || record_type == "LootRandomizer";
+    if !wanted_type {
+        return false;
+    }
+    if record_type.starts_with("Item") {
+        let ignore_list = [
+            "ItemTransmuter",
+            "ItemTransmuterSet",
+            "ItemSetFormula",
+            "ItemRandomSetFormula",
+        ];
+        if ignore_list.iter().any(|ign| record_type.starts_with(ign)) {
+            return false;
+        }
+    }
+
+    let wanted_path = record_name.starts_with("records/items/")
+        || record_name.starts_with("records/creatures/npcs/npcgear/")
+        || record_name.starts_with("records/storyelements/")
+        || record_name.starts_with("records/endlessdungeon/");
+    if !wanted_path {
+        return false;
+    }
+    let ignore_list = [
+        "records/items/enemygear/",
+        "records/items/transmutes/",
+        // Searching for unique affixes. Maybe later.
+        "records/items/lootaffixes/prefixunique/",
+        "records/items/lootaffixes/suffixunique/",
+        "records/items/lootaffixes/completionrelics",
+        "records/items/lootaffixes/completion",
+        "records/items/lootaffixes/crafting",
+    ];
+    !ignore_list.iter().any(|ign| record_name.starts_with(ign))
+}
+
+/// The values of one field of a record. Every field is an array, although most of them only hold one value.
+#[derive(Clone, Debug, PartialEq)]
+pub enum EntryValue {
+    Float(Vec<f32>),
+    Text(Vec<String>),
+    Int(Vec<u32>), // also used for booleans
+}
+
+impl EntryValue {
+    pub fn len(&self) -> usize {
+        match self {
+            Self::Float(values) => values.len(),
+            Self::Text(values) => values.len(),
+            Self::Int(values) => values.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// A fully decoded database record. Fields are kept in the order they are stored in.
+#[derive(Clone, Debug)]
+pub struct DbRecord {
+    pub name: String,
+    pub record_type: String,
+    pub fields: Vec<(String, EntryValue)>,
+}
+
+impl DbRecord {
+    pub fn get(&self, key: &str) -> Option<&EntryValue> {
+        self.fields.iter().find(|(k, _)| k == key).map(|(_, value)| value)
+    }
+
+    /// First value of a string field.
+    pub fn get_str(&self, key: &str) -> Option<&str> {
+        match self.get(key)? {
+            EntryValue::Text(values) => values.first().map(String::as_str),
+            _ => None,
+        }
+    }
+
+    /// First value of an integer or boolean field.
+    pub fn get_int(&self, key: &str) -> Option<u32> {
+        match self.get(key)? {
+            EntryValue::Int(values) => values.first().copied(),
+            _ => None,
+        }
+    }
+
+    /// First value of a float field.
+    pub fn get_float(&self, key: &str) -> Option<f32> {
+        match self.get(key)? {
+            EntryValue::Float(values) => values.first().copied(),
+            _ => None,
+        }
+    }
+
+    /// Format the record the way the official modding tools store them: one "key,value," line per field, with
+    /// array values separated by ';'.
+    pub fn to_dbr(&self) -> String {
+        let mut dbr = String::new();
+        for (key, value) in &self.fields {
+            let value = match value {
+                EntryValue::Float(values) => values.iter().map(|v| format!("{v:.6}")).collect::<Vec<_>>().join(";"),
+                EntryValue::Text(values) => values.join(";"),
+                EntryValue::Int(values) => values.iter().map(u32::to_string).collect::<Vec<_>>().join(";"),
+            };
+            dbr.push_str(&format!("{key},{value},\n"));
+        }
+        dbr
+    }
+}
+
+fn read_fields(
+    record_header: &ArzRecordHeader,
+    data: Vec<u8>,
+    strings: &[String],
+) -> Result<Vec<(String, EntryValue)>, GdError> {
+    let mut reader = ByteReader::from_vec(data);
+    let mut fields = Vec::new();
+
+    let mut i = 0;
+    while i < record_header.size_decompressed / 4 {
+        let entry_header = EntryHeader::read(&mut reader)?;
+        i += 2 + entry_header.entry_count as u32;
+        let entry_key = string_at(strings, &reader, entry_header.string_index)?.clone();
+        let count = entry_header.entry_count as usize;
+        let value = match entry_header.entry_type {
+            1 => EntryValue::Float((0..count).map(|_| reader.read_f32()).collect::<Result<_, _>>()?),
+            2 => EntryValue::Text(
+                (0..count)
+                    .map(|_| {
+                        let index = reader.read_u32()?;
+                        Ok(string_at(strings, &reader, index)?.clone())
+                    })
+                    .collect::<Result<_, GdError>>()?,
+            ),
+            _ => EntryValue::Int((0..count).map(|_| reader.read_u32()).collect::<Result<_, _>>()?),
+        };
+        fields.push((entry_key, value));
+    }
+    Ok(fields)
+}
+
+#[derive(Clone, Debug)]
 pub enum EntryType {
     Affix(AffixInfo),
-    Item(String, String, String, Option<u32>), // record name, tag name, rarity, level req
+    Item(String, String, String, Option<u32>, ItemCategory), // record name, tag name, rarity, level req, category
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct AffixInfo {
     pub tag_name: Option<String>,
     pub rarity: String, // the affixes could be printed in color with this
@@ -228,141 +415,125 @@ fn parse_record(
     record_name: &str,
     strings: &[String],
     is_affix: bool,
-) -> Option<EntryType> {
+) -> Result<Option<EntryType>, GdError> {
     let mut reader = ByteReader::from_vec(data);
 
-    let mut vals: Vec<(String, EntryValue)> = Vec::new();
     let mut tag_name: Option<String> = None; // used by most items and affixes
     let mut description: Option<String> = None; // fallback for relics that don't have itemNameTag
     let mut rarity: Option<String> = None;
     let mut level_req: Option<u32> = None;
 
-    //println!("Processing record: {record_name}");
-
     let mut i = 0;
     'outer: while i < record_header.size_decompressed / 4 {
-        let entry_header = EntryHeader::read(&mut reader);
+        let entry_header = EntryHeader::read(&mut reader)?;
         i += 2 + entry_header.entry_count as u32;
-        let entry_key = &strings[entry_header.string_index as usize];
-        //println!("entry key {entry_key}");
+        let entry_key = string_at(strings, &reader, entry_header.string_index)?;
         for _ in 0..entry_header.entry_count {
-            let entry_value = match entry_header.entry_type {
-                1 => EntryValue::Float(reader.read_f32()),
+            match entry_header.entry_type {
+                1 => {
+                    reader.read_f32()?;
+                }
                 2 => {
-                    let int = reader.read_u32();
-                    let value = &strings[int as usize];
-                    if value == "Mythical" {
-                        println!("{record_name} {entry_key}: {value}");
-                    }
+                    let int = reader.read_u32()?;
+                    let value = string_at(strings, &reader, int)?;
                     match entry_key.as_str() {
-                        "lootRandomizerName" | "itemNameTag" => {
-                            tag_name = Some(value.clone());
-                        }
-                        "itemClassification" => {
-                            rarity = Some(value.clone());
-                        }
-                        "description" => {
-                            description = Some(value.clone());
-                        }
+                        "lootRandomizerName" | "itemNameTag" => tag_name = Some(value.clone()),
+                        "itemClassification" => rarity = Some(value.clone()),
+                        "description" => description = Some(value.clone()),
                         _ => {}
                     }
-                    EntryValue::Text(value.clone())
                 }
                 _ => {
-                    let int = reader.read_u32();
+                    let int = reader.read_u32()?;
                     //Seems like the "levelRequirement" field isn't useful..?
                     if entry_key.as_str() == "itemLevel" {
                         level_req = Some(int);
                     }
-                    EntryValue::Int(int)
                 }
-            };
-
-            // Stop reading data once we found what we came for.
-            // We only need these fields for items
-            if !is_affix && tag_name.is_some() && level_req.is_some() && rarity.is_some() {
-                break 'outer;
             }
-            // We can also use the rarity for affixes to display them nicely in the UI
-            if tag_name.is_some() && rarity.is_some() {
+
+            // Stop reading data once we found what we came for. Affixes only need their name and rarity, items
+            // also their level, which tiers are ordered by.
+            if tag_name.is_some() && rarity.is_some() && (is_affix || level_req.is_some()) {
                 break 'outer;
             }
-
-            // These are actually only used when debugging
-            vals.push((entry_key.clone(), entry_value));
         }
     }
-    //if Some("tagGDX2ShoulderC203".to_string()) == tag_name {
-    //    println!("Baldir's Mantle!");
-    //    println!("{}", record_name);
-    //    for (key, val) in vals {
-    //        println!("{key}: {:?}", val);
-    //    }
-    //    println!("-----------");
-    //}
     let rarity = rarity.unwrap_or_default();
+    let category = ItemCategory::from_record_type(&record_header.record_type);
     if is_affix {
-        if tag_name.is_none() {
-            //println!("Nothing found for: {:?}", record_name);
-            //println!("{:?}", vals);
-        }
         let ai = AffixInfo {
             tag_name,
             rarity,
             name: None,
         };
-        Some(EntryType::Affix(ai))
+        Ok(Some(EntryType::Affix(ai)))
     } else {
-        //println!("{}, {record_name} {:?}", record.header.record_type, tag_name);
         if let Some(name) = tag_name {
-            return Some(EntryType::Item(record_name.to_string(), name.clone(), rarity, level_req));
-        } else if let Some(desc) = description {
-            if !desc.is_empty() {
-                //println!("No tag but had description: {}, {record_name} {:?}", record_header.record_type, tag_name);
-                return Some(EntryType::Item(record_name.to_string(), desc.clone(), rarity, level_req));
-            } else {
-                println!("Empty tag and description: {}, {record_name} {:?}", record_header.record_type, tag_name);
-            }
+            return Ok(Some(EntryType::Item(record_name.to_string(), name, rarity, level_req, category)));
+        } else if let Some(desc) = description
+            && !desc.is_empty()
+        {
+            return Ok(Some(EntryType::Item(record_name.to_string(), desc, rarity, level_req, category)));
         }
-        // Uncomment to debug what is getting parsed
-        //println!("No tagname found for {record_name}.", );
-        //for (key, val) in vals {
-        //    println!("{key}: {:?}", val);
-        //}
         // we tried everything, so maybe use record_name as tag
-        Some(EntryType::Item(record_name.to_string(), record_name.to_string(), rarity, level_req))
+        Ok(Some(EntryType::Item(record_name.to_string(), record_name.to_string(), rarity, level_req, category)))
     }
 }
 
-fn decompress(byte_vec: &mut ByteReader, header: &ArzRecordHeader) -> Vec<u8> {
+/// Look up a string table entry by an index read from the file.
+fn string_at<'a>(strings: &'a [String], reader: &ByteReader, index: u32) -> Result<&'a String, GdError> {
+    strings
+        .get(index as usize)
+        .ok_or_else(|| reader.unexpected_value("string index", format!("less than {}", strings.len()), index))
+}
+
+fn decompress(byte_vec: &mut ByteReader, header: &ArzRecordHeader) -> Result<Vec<u8>, GdError> {
     byte_vec.index = header.offset as usize + 24;
-    let end = byte_vec.index + header.size_compressed as usize;
-    let slice = &byte_vec.bytes[byte_vec.index..end];
-    lz4::block::decompress(slice, Some(header.size_decompressed.try_into().unwrap())).unwrap()
+    let offset = byte_vec.index;
+    let compressed = byte_vec.read_n_bytes(header.size_compressed)?;
+    if !lz4_size_is_plausible(header.size_compressed, header.size_decompressed) {
+        return Err(GdError::Decompress {
+            path: byte_vec.path().clone(),
+            offset,
+            reason: format!("{} bytes can't decompress to {}", header.size_compressed, header.size_decompressed),
+        });
+    }
+    // lz4 takes the size as an i32
+    let size_decompressed = header.size_decompressed.try_into().map_err(|_| GdError::Decompress {
+        path: byte_vec.path().clone(),
+        offset,
+        reason: format!("{} bytes is too big to decompress to", header.size_decompressed),
+    })?;
+    lz4::block::decompress(&compressed, Some(size_decompressed)).map_err(|e| GdError::Decompress {
+        path: byte_vec.path().clone(),
+        offset,
+        reason: e.to_string(),
+    })
 }
 
-fn read_record_headers(byte_vec: &mut ByteReader, header: &ArzArchiveHeader) -> Vec<ArzRecordHeader> {
+fn read_record_headers(byte_vec: &mut ByteReader, header: &ArzArchiveHeader) -> Result<Vec<ArzRecordHeader>, GdError> {
     let mut records = Vec::new();
     byte_vec.index = header.records_start as usize;
     for _ in 0..header.records_count {
-        let record = ArzRecordHeader::read(byte_vec);
+        let record = ArzRecordHeader::read(byte_vec)?;
         records.push(record);
         byte_vec.index += 8;
     }
-    records
+    Ok(records)
 }
 
-fn read_strings(byte_vec: &mut ByteReader, header: &ArzArchiveHeader) -> Vec<String> {
+fn read_strings(byte_vec: &mut ByteReader, header: &ArzArchiveHeader) -> Result<Vec<String>, GdError> {
     let mut strings = Vec::new();
     byte_vec.index = (header.strings_start) as usize;
-    let end = (header.strings_start + header.strings_size) as usize;
+    let end = header.strings_start as usize + header.strings_size as usize;
     while byte_vec.index < end {
-        let count = byte_vec.read_u32();
+        let count = byte_vec.read_u32()?;
         for _ in 0..count {
-            let len = byte_vec.read_u32();
-            let string = byte_vec.read_string(len);
+            let len = byte_vec.read_u32()?;
+            let string = byte_vec.read_string(len)?;
             strings.push(string);
         }
     }
-    strings
+    Ok(strings)
 }
@@ -0,0 +1,309 @@
+use crate::arc_parser::format_filetime;
+use crate::encrypt;
+use crate::error::GdError;
+
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+const MANIFEST: &str = "manifest.txt";
+
+/// A save file as it was backed up, and as gdlc wrote it afterwards.
+#[derive(Clone, Debug)]
+pub struct BackupFile {
+    /// Relative to the save directory, eg. "main/_Alina/player.gdc"
+    pub path: PathBuf,
+    pub size: u64,
+    pub checksum: u64,
+    /// Checksum of what gdlc wrote, None if it didn't get to write the file
+    pub written: Option<u64>,
+}
+
+/// A snapshot of the save files that one command changed, taken before it changed them.
+pub struct Backup {
+    pub id: String,
+    pub reason: String,
+    pub files: Vec<BackupFile>,
+    dir: PathBuf,
+    save_dir: PathBuf,
+}
+
+/// The backup directory. Each snapshot is a subdirectory named by the time it was taken, with copies of the files
+/// and a manifest of their checksums.
+pub struct Backups {
+    dir: PathBuf,
+    save_dir: PathBuf,
+    keep: usize,
+}
+
+impl Backups {
+    /// Only the newest "keep" snapshots are kept.
+    pub fn new(dir: PathBuf, save_dir: PathBuf, keep: usize) -> Self {
+        Self {
+            dir,
+            save_dir,
+            keep: keep.max(1),
+        }
+    }
+
+    /// Copy the files, which need to be in the save directory, into a new snapshot. Older snapshots beyond the limit
+    /// are deleted.
+    pub fn create(&self, reason: &str, paths: &[&Path]) -> Result<Backup, GdError> {
+        let mut id = snapshot_id(SystemTime::now());
+        // Two commands within the same second
+        let mut n = 1;
+        while self.dir.join(&id).exists() {
+            n += 1;
+            id = format!("{}-{n}", snapshot_id(SystemTime::now()));
+        }
+        let dir = self.dir.join(&id);
+
+        let mut files = Vec::new();
+        for path in paths {
+            let Ok(relative_path) = path.strip_prefix(&self.save_dir) else {
+                let e = io::Error::new(io::ErrorKind::InvalidInput, "only files in the save directory are backed up");
+                return Err(GdError::io(path, e));
+            };
+            if files.iter().any(|file: &BackupFile| file.path == relative_path) {
+                continue;
+            }
+            let bytes = fs::read(path).map_err(|e| GdError::io(path, e))?;
+            let copy_path = dir.join(relative_path);
+            if let Some(parent) = copy_path.parent() {
+                fs::create_dir_all(parent).map_err(|e| GdError::io(parent, e))?;
+            }
+            fs::write(&copy_path, &bytes).map_err(|e| GdError::io(&copy_path, e))?;
+            files.push(BackupFile {
+                path: relative_path.to_path_buf(),
+                size: bytes.len() as u64,
+                checksum: checksum(&bytes),
+                written: None,
+            });
+        }
+
+        let backup = Backup {
+            id,
+            reason: reason.replace('\n', " "),
+            files,
+            dir,
+            save_dir: self.save_dir.clone(),
+        };
+        backup.write_manifest()?;
+        self.rotate()?;
+        Ok(backup)
+    }
+
+    /// Every snapshot, oldest first.
+    pub fn list(&self) -> Result<Vec<Backup>, GdError> {
+        let mut ids = Vec::new();
+        match fs::read_dir(&self.dir) {
+            Ok(read_dir) => {
+                for entry in read_dir {
+                    let entry = entry.map_err(|e| GdError::io(&self.dir, e))?;
+                    if entry.path().join(MANIFEST).exists() {
+                        ids.push(entry.file_name().to_string_lossy().to_string());
+                    }
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => (),
+            Err(e) => return Err(GdError::io(&self.dir, e)),
+        }
+        // The ids start with the time, so they sort by age
+        ids.sort();
+        ids.iter().map(|id| self.read(id)).collect()
+    }
+
+    pub fn get(&self, id: &str) -> Result<Option<Backup>, GdError> {
+        // Ids come from the command line, don't let them point outside the backup directory
+        if id.is_empty() || id.contains(['/', '\\']) || id == "." || id == ".." {
+            return Ok(None);
+        }
+        if !self.dir.join(id).join(MANIFEST).exists() {
+            return Ok(None);
+        }
+        self.read(id).map(Some)
+    }
+
+    fn read(&self, id: &str) -> Result<Backup, GdError> {
+        let dir = self.dir.join(id);
+        let manifest_path = dir.join(MANIFEST);
+        let manifest = fs::read_to_string(&manifest_path).map_err(|e| GdError::io(&manifest_path, e))?;
+        let invalid = |line_number: usize| {
+            let e = io::Error::new(io::ErrorKind::InvalidData, format!("invalid manifest line {}", line_number + 1));
+            GdError::io(&manifest_path, e)
+        };
+
+        let mut reason = String::new();
+        let mut files = Vec::new();
+        for (line_number, line) in manifest.lines().enumerate() {
+            match line.split_once('=') {
+                Some(("reason", value)) => reason = value.to_string(),
+                // file=<checksum>,<written checksum or ->,<size>,<path>
+                Some(("file", value)) => {
+                    let fields: Vec<&str> = value.splitn(4, ',').collect();
+                    let [checksum, written, size, path] = fields[..] else {
+                        return Err(invalid(line_number));
+                    };
+                    let parse_checksum = |hex| u64::from_str_radix(hex, 16).map_err(|_| invalid(line_number));
+                    files.push(BackupFile {
+                        path: PathBuf::from(path),
+                        size: size.parse().map_err(|_| invalid(line_number))?,
+                        checksum: parse_checksum(checksum)?,
+                        written: if written == "-" { None } else { Some(parse_checksum(written)?) },
+                    });
+                }
+                _ if line.is_empty() || line.starts_with('#') => (),
+                _ => return Err(invalid(line_number)),
+            }
+        }
+        Ok(Backup {
+            id: id.to_string(),
+            reason,
+            files,
+            dir,
+            save_dir: self.save_dir.clone(),
+        })
+    }
+
+    /// Put the backed up copies of the files back. What they replace is backed up first, the new snapshot is returned
+    /// so that the restore can be undone too.
+    pub fn restore(&self, backup: &Backup, files: &[&BackupFile]) -> Result<Backup, GdError> {
+        // Every copy is read before the new snapshot is taken, which may rotate away the one being restored. A
+        // damaged copy also stops the restore before anything changed.
+        let mut copies = Vec::new();
+        let mut existing = Vec::new();
+        for file in files {
+            let path = backup.save_path(file);
+            if backup.state(file)? != FileState::Missing {
+                existing.push(path.clone());
+            }
+            copies.push((path, backup.copy(file)?));
+        }
+        let existing: Vec<&Path> = existing.iter().map(PathBuf::as_path).collect();
+        let mut undo = self.create(&format!("restore {}", backup.id), &existing)?;
+        for (path, bytes) in copies {
+            if existing.contains(&path.as_path()) {
+                undo.write(&path, &bytes)?;
+            } else {
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent).map_err(|e| GdError::io(parent, e))?;
+                }
+                encrypt::replace_file(&path, &bytes)?;
+            }
+        }
+        Ok(undo)
+    }
+
+    fn rotate(&self) -> Result<(), GdError> {
+        let ids: Vec<String> = self.list()?.into_iter().map(|backup| backup.id).collect();
+        for id in &ids[..ids.len().saturating_sub(self.keep)] {
+            let dir = self.dir.join(id);
+            fs::remove_dir_all(&dir).map_err(|e| GdError::io(&dir, e))?;
+        }
+        Ok(())
+    }
+}
+
+/// What a file in the save directory looks like compared to its backup.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FileState {
+    /// Same as the backup, there's nothing to restore
+    Unchanged,
+    /// As gdlc wrote it
+    Written,
+    /// Changed by something else since, most likely the game
+    ChangedSince,
+    Missing,
+}
+
+impl Backup {
+    /// Replace a file in the save directory and note in the manifest what was written. Use this for every file that
+    /// gdlc changes, after backing it up.
+    pub fn write(&mut self, path: &Path, bytes: &[u8]) -> Result<(), GdError> {
+        let Some(file) = self.files.iter_mut().find(|file| self.save_dir.join(&file.path) == path) else {
+            let e = io::Error::new(io::ErrorKind::InvalidInput, "the file wasn't backed up before writing it");
+            return Err(GdError::io(path, e));
+        };
+        file.written = Some(checksum(bytes));
+        // The manifest goes first, if gdlc dies in between a restore just warns about a changed file
+        self.write_manifest()?;
+        encrypt::replace_file(path, bytes)
+    }
+
+    pub fn state(&self, file: &BackupFile) -> Result<FileState, GdError> {
+        let path = self.save_path(file);
+        let bytes = match fs::read(&path) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(FileState::Missing),
+            Err(e) => return Err(GdError::io(&path, e)),
+        };
+        let current = checksum(&bytes);
+        Ok(if current == file.checksum {
+            FileState::Unchanged
+        } else if Some(current) == file.written {
+            FileState::Written
+        } else {
+            FileState::ChangedSince
+        })
+    }
+
+    /// Where the file belongs in the save directory.
+    pub fn save_path(&self, file: &BackupFile) -> PathBuf {
+        self.save_dir.join(&file.path)
+    }
+
+    /// The backed up copy of a file, checked against the manifest.
+    pub fn copy(&self, file: &BackupFile) -> Result<Vec<u8>, GdError> {
+        let copy_path = self.dir.join(&file.path);
+        let bytes = fs::read(&copy_path).map_err(|e| GdError::io(&copy_path, e))?;
+        if checksum(&bytes) != file.checksum {
+            let e = io::Error::new(io::ErrorKind::InvalidData, "the backup copy doesn't match its checksum");
+            return Err(GdError::io(&copy_path, e));
+        }
+        Ok(bytes)
+    }
+
+    /// When the snapshot was taken, "YYYY-MM-DD hh:mm:ss" in UTC.
+    pub fn created(&self) -> String {
+        let (date, time) = self.id.split_at(self.id.find('_').unwrap_or(self.id.len()));
+        let time = time.trim_start_matches('_');
+        if time.len() < 6 {
+            return self.id.clone();
+        }
+        format!("{date} {}:{}:{}", &time[0..2], &time[2..4], &time[4..6])
+    }
+
+    fn write_manifest(&self) -> Result<(), GdError> {
+        let mut manifest = String::from("# gdlc backup, checksums are 64 bit FNV-1a\n");
+        manifest.push_str(&format!("reason={}\n", self.reason));
+        for file in &self.files {
+            let written = file.written.map_or_else(|| "-".to_string(), |written| format!("{written:016x}"));
+            let path = file.path.to_string_lossy().replace('\\', "/");
+            manifest.push_str(&format!("file={:016x},{written},{},{path}\n", file.checksum, file.size));
+        }
+        fs::create_dir_all(&self.dir).map_err(|e| GdError::io(&self.dir, e))?;
+        let path = self.dir.join(MANIFEST);
+        encrypt::replace_file(&path, manifest.as_bytes())
+    }
+}
+
+/// Snapshot directory name, eg. "2024-05-01_134502". UTC, so that it sorts the same in every time zone.
+fn snapshot_id(time: SystemTime) -> String {
+    // Seconds between 1601-01-01 and 1970-01-01
+    const EPOCH_DIFFERENCE: u64 = 11_644_473_600;
+    let unix_secs = time.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    format_filetime((unix_secs + EPOCH_DIFFERENCE) * 10_000_000).replace(' ', "_").replace(':', "")
+}
+
+/// 64 bit FNV-1a. Enough to tell whether a file changed, and whether a backup copy got damaged.
+pub fn checksum(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for &byte in bytes {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
@@ -1,78 +1,113 @@
-use std::io::Error;
+use crate::error::GdError;
+
 use std::io::Read;
 use std::fs::File;
 use std::path::PathBuf;
 use std::sync::Arc;
 
-// Allow cloning the reader to have multiple views into the same underlying data
+// Allow cloning the reader to have multiple views into the same underlying data.
+// All values are little-endian, regardless of the host.
 #[derive(Clone)]
 pub struct ByteReader {
     pub bytes: Arc<Vec<u8>>,
     pub index: usize,
+    path: Arc<PathBuf>, // empty if the bytes didn't come from a file
 }
 
 impl ByteReader {
-    pub fn from_file(path: &PathBuf) -> Result<Self, Error> {
-        let mut file = File::open(path)?;
+    pub fn from_file(path: &PathBuf) -> Result<Self, GdError> {
+        let mut file = File::open(path).map_err(|e| GdError::io(path, e))?;
         let mut bytes = Vec::new();
-        let _len = file.read_to_end(&mut bytes)?;
-        Ok(Self { bytes: Arc::new(bytes), index: 0 })
+        let _len = file.read_to_end(&mut bytes).map_err(|e| GdError::io(path, e))?;
+        Ok(Self { bytes: Arc::new(bytes), index: 0, path: Arc::new(path.clone()) })
     }
 
     pub fn from_vec(bytes: Vec<u8>) -> Self {
         Self {
             bytes: Arc::new(bytes),
-            index: 0
+            index: 0,
+            path: Arc::new(PathBuf::new()),
         }
     }
 
-    pub fn read_byte(&mut self) -> u8 {
-        let ret = self.bytes[self.index];
-        self.index += 1;
-        ret
+    pub fn path(&self) -> &PathBuf {
+        &self.path
     }
 
-    pub fn read_u16(&mut self) -> u16 {
-        let new_index = self.index + 2;
-        let ret =
-            u16::from_ne_bytes(<[u8; 2]>::try_from(&self.bytes[self.index..new_index]).unwrap());
-        self.index = new_index;
-        ret
+    /// Offset of the next byte to be read.
+    pub fn position(&self) -> usize {
+        self.index
     }
 
-    pub fn read_u32(&mut self) -> u32 {
-        let new_index = self.index + 4;
-        let ret =
-            u32::from_ne_bytes(<[u8; 4]>::try_from(&self.bytes[self.index..new_index]).unwrap());
-        self.index = new_index;
-        ret
+    /// How many bytes are left to be read.
+    pub fn remaining(&self) -> usize {
+        self.bytes.len().saturating_sub(self.index)
     }
 
-    pub fn read_f32(&mut self) -> f32 {
-        let new_index = self.index + 4;
-        let ret =
-            f32::from_ne_bytes(<[u8; 4]>::try_from(&self.bytes[self.index..new_index]).unwrap());
-        self.index = new_index;
-        ret
+    /// Capacity to reserve for "count" values that take at least "min_size" bytes each. The count usually comes from
+    /// the file itself, so it can't be trusted to allocate up front.
+    pub fn capacity_for(&self, count: u32, min_size: usize) -> usize {
+        (count as usize).min(self.remaining() / min_size.max(1))
     }
 
-    pub fn read_u64(&mut self) -> u64 {
-        let new_index = self.index + 8;
-        let ret =
-            u64::from_ne_bytes(<[u8; 8]>::try_from(&self.bytes[self.index..new_index]).unwrap());
-        self.index = new_index;
-        ret
+    /// An error for a value at the current position that isn't what the format says it should be.
+    pub fn unexpected_value(&self, field: &'static str, expected: impl ToString, found: impl ToString) -> GdError {
+        GdError::UnexpectedValue {
+            path: self.path.to_path_buf(),
+            offset: self.index,
+            block: None,
+            field,
+            expected: expected.to_string(),
+            found: found.to_string(),
+        }
     }
 
-    pub fn read_n_bytes(&mut self, n: u32) -> Vec<u8> {
-        let n = n as usize;
-        let ret = &self.bytes[self.index..self.index + n];
+    fn take(&mut self, n: usize) -> Result<&[u8], GdError> {
+        // The index can be set to anything from an offset in the file, so it may already be past the end
+        let start = self.index;
+        let Some(ret) = start.checked_add(n).and_then(|end| self.bytes.get(start..end)) else {
+            return Err(GdError::UnexpectedEof {
+                path: self.path.to_path_buf(),
+                offset: start,
+                block: None,
+                needed: n.saturating_sub(self.remaining()).max(1),
+            });
+        };
         self.index += n;
-        ret.to_vec()
+        Ok(ret)
+    }
+
+    pub fn read_byte(&mut self) -> Result<u8, GdError> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn read_u16(&mut self) -> Result<u16, GdError> {
+        Ok(u16::from_le_bytes(<[u8; 2]>::try_from(self.take(2)?).unwrap()))
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32, GdError> {
+        Ok(u32::from_le_bytes(<[u8; 4]>::try_from(self.take(4)?).unwrap()))
     }
 
-    pub fn read_string(&mut self, len: u32) -> String {
-        String::from_utf8(self.read_n_bytes(len)).unwrap()
+    pub fn read_f32(&mut self) -> Result<f32, GdError> {
+        Ok(f32::from_le_bytes(<[u8; 4]>::try_from(self.take(4)?).unwrap()))
+    }
+
+    pub fn read_u64(&mut self) -> Result<u64, GdError> {
+        Ok(u64::from_le_bytes(<[u8; 8]>::try_from(self.take(8)?).unwrap()))
+    }
+
+    pub fn read_n_bytes(&mut self, n: u32) -> Result<Vec<u8>, GdError> {
+        Ok(self.take(n as usize)?.to_vec())
+    }
+
+    pub fn read_string(&mut self, len: u32) -> Result<String, GdError> {
+        let offset = self.index;
+        String::from_utf8(self.read_n_bytes(len)?).map_err(|_| GdError::InvalidString {
+            path: self.path.to_path_buf(),
+            offset,
+            block: None,
+        })
     }
 
     pub fn read_null_string(&mut self) -> Option<String> {
@@ -81,7 +116,8 @@ impl ByteReader {
         }
         let mut buf = Vec::new();
         while self.index < self.bytes.len() {
-            let byte = self.read_byte();
+            let byte = self.bytes[self.index];
+            self.index += 1;
             if byte == 0 {
                 break;
             }
@@ -90,3 +126,9 @@ impl ByteReader {
         Some(String::from_utf8_lossy(&buf).to_string())
     }
 }
+
+/// LZ4 can't compress better than about 255:1, so anything claiming more is corrupt. Checked before allocating the
+/// output buffer.
+pub(crate) fn lz4_size_is_plausible(len_compressed: u32, len_decompressed: u32) -> bool {
+    u64::from(len_decompressed) <= u64::from(len_compressed) * 255 + 16
+}
@@ -0,0 +1,199 @@
+use crate::arz_parser::AffixInfo;
+use crate::arz_parser::EntryType;
+use crate::byte_reader::ByteReader;
+use crate::encrypt;
+use crate::error::GdError;
+use crate::item_category::ItemCategory;
+use crate::item_search::LocalizationStrings;
+use crate::item_search::TagEntries;
+
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::UNIX_EPOCH;
+
+const MAGIC: &[u8; 4] = b"GDLC";
+// Bump this whenever the contents of TagEntries or the layout below change
+const VERSION: u32 = 6;
+
+/// Size and modification time of every game file the cached data was read from. The cache is only used if all of
+/// them are unchanged.
+#[derive(Debug, PartialEq)]
+pub struct CacheKey {
+    files: Vec<(String, u64, u64, u32)>, // path, size, mtime seconds, mtime nanoseconds
+}
+
+impl CacheKey {
+    pub fn new(paths: &[PathBuf]) -> Result<Self, GdError> {
+        let mut files = Vec::new();
+        for path in paths {
+            let metadata = fs::metadata(path).map_err(|e| GdError::io(path, e))?;
+            let mtime = metadata.modified().map_err(|e| GdError::io(path, e))?;
+            // Anything before 1970 is as good as no mtime
+            let mtime = mtime.duration_since(UNIX_EPOCH).unwrap_or_default();
+            files.push((path.to_string_lossy().to_string(), metadata.len(), mtime.as_secs(), mtime.subsec_nanos()));
+        }
+        Ok(Self { files })
+    }
+
+    fn read(reader: &mut ByteReader) -> Result<Self, GdError> {
+        let count = reader.read_u32()?;
+        let mut files = Vec::with_capacity(reader.capacity_for(count, 24));
+        for _ in 0..count {
+            files.push((read_string(reader)?, reader.read_u64()?, reader.read_u64()?, reader.read_u32()?));
+        }
+        Ok(Self { files })
+    }
+
+    fn write(&self, buf: &mut Vec<u8>) {
+        write_u32(buf, self.files.len() as u32);
+        for (path, size, secs, nanos) in &self.files {
+            write_string(buf, path);
+            buf.extend_from_slice(&size.to_le_bytes());
+            buf.extend_from_slice(&secs.to_le_bytes());
+            write_u32(buf, *nanos);
+        }
+    }
+}
+
+/// Read the cached database records and localization data. None if there is no cache, or it was made from
+/// different game files or by a different version of gdlc.
+pub fn load(path: &Path, key: &CacheKey) -> Result<Option<(TagEntries, LocalizationStrings)>, GdError> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let mut reader = ByteReader::from_file(&path.to_path_buf())?;
+    if reader.read_n_bytes(4)? != MAGIC || reader.read_u32()? != VERSION || CacheKey::read(&mut reader)? != *key {
+        return Ok(None);
+    }
+
+    let mut entries = TagEntries::new();
+    let count = reader.read_u32()?;
+    for _ in 0..count {
+        let record_name = read_string(&mut reader)?;
+        let entry = match reader.read_byte()? {
+            0 => None,
+            1 => Some(EntryType::Item(
+                read_string(&mut reader)?,
+                read_string(&mut reader)?,
+                read_string(&mut reader)?,
+                read_option_u32(&mut reader)?,
+                read_category(&mut reader)?,
+            )),
+            2 => Some(EntryType::Affix(AffixInfo {
+                tag_name: read_option_string(&mut reader)?,
+                rarity: read_string(&mut reader)?,
+                name: read_option_string(&mut reader)?,
+            })),
+            kind => return Err(reader.unexpected_value("entry kind", "0, 1 or 2", kind)),
+        };
+        entries.insert(record_name, entry);
+    }
+
+    let mut localization = LocalizationStrings::new();
+    let count = reader.read_u32()?;
+    for _ in 0..count {
+        localization.insert(read_string(&mut reader)?, read_string(&mut reader)?);
+    }
+    Ok(Some((entries, localization)))
+}
+
+/// Write the cache file, replacing any old one.
+pub fn save(
+    path: &Path,
+    key: &CacheKey,
+    entries: &TagEntries,
+    localization: &LocalizationStrings,
+) -> Result<(), GdError> {
+    let mut buf = Vec::new();
+    buf.extend_from_slice(MAGIC);
+    write_u32(&mut buf, VERSION);
+    key.write(&mut buf);
+
+    write_u32(&mut buf, entries.len() as u32);
+    for (record_name, entry) in entries {
+        write_string(&mut buf, record_name);
+        match entry {
+            None => buf.push(0),
+            Some(EntryType::Item(item_record_name, tag_name, rarity, level_req, category)) => {
+                buf.push(1);
+                write_string(&mut buf, item_record_name);
+                write_string(&mut buf, tag_name);
+                write_string(&mut buf, rarity);
+                write_option_u32(&mut buf, *level_req);
+                write_string(&mut buf, category.name());
+            }
+            Some(EntryType::Affix(affix)) => {
+                buf.push(2);
+                write_option_string(&mut buf, affix.tag_name.as_deref());
+                write_string(&mut buf, &affix.rarity);
+                write_option_string(&mut buf, affix.name.as_deref());
+            }
+        }
+    }
+
+    write_u32(&mut buf, localization.len() as u32);
+    for (tag, text) in localization {
+        write_string(&mut buf, tag);
+        write_string(&mut buf, text);
+    }
+
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| GdError::io(dir, e))?;
+    }
+    // Swapped in whole, so that a concurrent gdlc never sees half a cache
+    encrypt::replace_file(path, &buf)
+}
+
+fn read_string(reader: &mut ByteReader) -> Result<String, GdError> {
+    let len = reader.read_u32()?;
+    reader.read_string(len)
+}
+
+fn read_option_string(reader: &mut ByteReader) -> Result<Option<String>, GdError> {
+    match reader.read_byte()? {
+        0 => Ok(None),
+        _ => Ok(Some(read_string(reader)?)),
+    }
+}
+
+fn read_option_u32(reader: &mut ByteReader) -> Result<Option<u32>, GdError> {
+    match reader.read_byte()? {
+        0 => Ok(None),
+        _ => Ok(Some(reader.read_u32()?)),
+    }
+}
+
+fn read_category(reader: &mut ByteReader) -> Result<ItemCategory, GdError> {
+    let name = read_string(reader)?;
+    ItemCategory::parse(&name).ok_or_else(|| reader.unexpected_value("item category", "a category name", name))
+}
+
+fn write_u32(buf: &mut Vec<u8>, value: u32) {
+    buf.extend_from_slice(&value.to_le_bytes());
+}
+
+fn write_string(buf: &mut Vec<u8>, string: &str) {
+    write_u32(buf, string.len() as u32);
+    buf.extend_from_slice(string.as_bytes());
+}
+
+fn write_option_string(buf: &mut Vec<u8>, string: Option<&str>) {
+    match string {
+        None => buf.push(0),
+        Some(string) => {
+            buf.push(1);
+            write_string(buf, string);
+        }
+    }
+}
+
+fn write_option_u32(buf: &mut Vec<u8>, value: Option<u32>) {
+    match value {
+        None => buf.push(0),
+        Some(value) => {
+            buf.push(1);
+            write_u32(buf, value);
+        }
+    }
+}
@@ -0,0 +1,343 @@
+use gdlc::query::Query;
+
+use std::path::PathBuf;
+
+pub enum Command {
+    /// List items matching the query, or all items if it's empty.
+    Search(Query, SearchOptions),
+    /// List every character with their class, level, attributes and save file.
+    Characters,
+    /// Show what a character has equipped, slot by slot.
+    Gear(String),
+    /// Move the item matching the query into a stash tab. If several items match, the number picks one.
+    Move(Query, Destination, Option<usize>),
+    /// List the backups taken before save files were changed.
+    BackupList,
+    /// Restore a backup, or one file of it. True restores files the game changed since.
+    BackupRestore(String, Option<String>, bool),
+    /// Extract the game database archives into .dbr text files.
+    DbDump(PathBuf),
+    /// List the files in an ARC archive.
+    ArcList(PathBuf),
+    /// Extract one file, or every file, from an ARC archive into the current directory.
+    ArcExtract(PathBuf, Option<String>),
+}
+
+/// A stash tab as search results name it, eg. "Softcore stash tab 3" or "Alina stash tab 2". Bags can't be one, the
+/// save files don't say how big they are.
+pub struct Destination {
+    /// "Softcore" or "Hardcore" for the shared stashes, otherwise a character name
+    pub owner: String,
+    /// Counted from 1
+    pub tab: usize,
+}
+
+impl Destination {
+    fn parse(words: &[String]) -> Option<Self> {
+        let (tab, mut words) = words.split_last()?;
+        let tab = tab.parse().ok().filter(|&tab| tab > 0)?;
+        if let Some((last, rest)) = words.split_last()
+            && last.eq_ignore_ascii_case("tab")
+        {
+            words = rest;
+        }
+        let (last, owner) = words.split_last()?;
+        if !last.eq_ignore_ascii_case("stash") || owner.is_empty() {
+            return None;
+        }
+        Some(Self { owner: owner.join(" "), tab })
+    }
+}
+
+/// Everything about a search but the query.
+#[derive(Default)]
+pub struct SearchOptions {
+    pub filters: Filters,
+    pub sort: SortOrder,
+    /// None to list every copy on its own line
+    pub group: Option<Grouping>,
+    /// Words may have typos, and the closest matches come first
+    pub fuzzy: bool,
+}
+
+/// Which copies --group counts as the same item.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Grouping {
+    /// Same base record and affixes
+    Item,
+    /// Same base record, whatever the affixes
+    Base,
+}
+
+/// Which characters and containers a search looks through.
+#[derive(Default)]
+pub struct Filters {
+    /// Lower case. Empty for every character
+    pub characters: Vec<String>,
+    /// Lower case
+    pub excluded_characters: Vec<String>,
+    /// Some(true) for only hardcore, Some(false) for only softcore
+    pub hardcore: Option<bool>,
+    /// Empty for every kind
+    pub only: Vec<ContainerKind>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ContainerKind {
+    /// Equipment and both weapon sets
+    Equipped,
+    /// The character's bags
+    Inventory,
+    /// The character's own stash
+    Stash,
+    /// The shared stashes
+    Shared,
+}
+
+impl ContainerKind {
+    fn parse(kind: &str) -> Option<Self> {
+        match kind {
+            "equipped" => Some(Self::Equipped),
+            "inventory" => Some(Self::Inventory),
+            "stash" => Some(Self::Stash),
+            "shared" => Some(Self::Shared),
+            _ => None,
+        }
+    }
+}
+
+impl Filters {
+    /// Whether a character's items are searched at all.
+    pub fn includes_character(&self, name: &str, hardcore: bool) -> bool {
+        let name = name.to_lowercase();
+        (self.characters.is_empty() || self.characters.contains(&name))
+            && !self.excluded_characters.contains(&name)
+            && self.hardcore.is_none_or(|only_hardcore| only_hardcore == hardcore)
+    }
+
+    /// Shared stashes don't belong to a character, so they're left out when searching particular characters, unless
+    /// asked for with --only shared.
+    pub fn includes_shared_stash(&self, hardcore: bool) -> bool {
+        let wanted = if self.only.is_empty() {
+            self.characters.is_empty()
+        } else {
+            self.only.contains(&ContainerKind::Shared)
+        };
+        wanted && self.hardcore.is_none_or(|only_hardcore| only_hardcore == hardcore)
+    }
+
+    pub fn includes(&self, kind: ContainerKind) -> bool {
+        self.only.is_empty() || self.only.contains(&kind)
+    }
+}
+
+/// How search results are ordered. Ties are broken by location.
+#[derive(Clone, Copy, Default)]
+pub struct SortOrder {
+    pub key: SortKey,
+    pub reverse: bool,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum SortKey {
+    /// Shared stashes first, then by character, container, tab and position
+    #[default]
+    Location,
+    /// Prefix, name and suffix
+    Name,
+    /// From common to legendary
+    Rarity,
+    /// Required level, items without one first
+    Level,
+}
+
+impl SortKey {
+    fn parse(key: &str) -> Option<Self> {
+        match key {
+            "location" => Some(Self::Location),
+            "name" => Some(Self::Name),
+            "rarity" => Some(Self::Rarity),
+            "level" => Some(Self::Level),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum OutputFormat {
+    /// For people, with colors on a terminal
+    #[default]
+    Table,
+    /// One array
+    Json,
+    /// One object per line
+    Ndjson,
+    Csv,
+}
+
+impl OutputFormat {
+    fn parse(format: &str) -> Option<Self> {
+        match format {
+            "table" => Some(Self::Table),
+            "json" => Some(Self::Json),
+            "ndjson" => Some(Self::Ndjson),
+            "csv" => Some(Self::Csv),
+            _ => None,
+        }
+    }
+}
+
+/// Options that apply to every command.
+pub struct Args {
+    pub command: Command,
+    /// Read the game files even if there is an up to date cache, and don't update it.
+    pub no_cache: bool,
+    /// How many files or records to read at the same time. Defaults to the number of CPUs.
+    pub jobs: Option<usize>,
+    /// How search results and characters are printed.
+    pub format: OutputFormat,
+}
+
+pub fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args, String> {
+    let mut no_cache = false;
+    let mut jobs = None;
+    let mut format = OutputFormat::default();
+    let mut rest = Vec::new();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--no-cache" => no_cache = true,
+            "-j" | "--jobs" => jobs = Some(parse_jobs(args.next())?),
+            "--format" => format = parse_format(args.next())?,
+            _ => {
+                if let Some(value) = arg.strip_prefix("--jobs=") {
+                    jobs = Some(parse_jobs(Some(value.to_string()))?);
+                } else if let Some(value) = arg.strip_prefix("--format=") {
+                    format = parse_format(Some(value.to_string()))?;
+                } else {
+                    rest.push(arg);
+                }
+            }
+        }
+    }
+    let command = parse_command(&rest)?;
+    Ok(Args { command, no_cache, jobs, format })
+}
+
+fn parse_format(value: Option<String>) -> Result<OutputFormat, String> {
+    value
+        .and_then(|format| OutputFormat::parse(&format.to_lowercase()))
+        .ok_or_else(|| "--format needs one of table, json, ndjson or csv, eg. --format json".to_string())
+}
+
+fn parse_jobs(value: Option<String>) -> Result<usize, String> {
+    match value.as_deref().map(str::parse) {
+        Some(Ok(jobs)) if jobs > 0 => Ok(jobs),
+        _ => Err("--jobs needs a number greater than 0, eg. --jobs 4".to_string()),
+    }
+}
+
+fn parse_command(args: &[String]) -> Result<Command, String> {
+    match args.first().map(String::as_str) {
+        Some("characters") => match args.len() {
+            1 => Ok(Command::Characters),
+            _ => Err("Usage: gdlc characters [--format table|json|ndjson|csv]".to_string()),
+        },
+        Some("gear") => match args {
+            [_, name] => Ok(Command::Gear(name.clone())),
+            _ => Err("Usage: gdlc gear <character>".to_string()),
+        },
+        Some("move") => {
+            let usage = "Usage: gdlc move <query> [--pick N] --to <Softcore|Hardcore|character> stash tab <N>";
+            let mut pick = None;
+            let mut words = Vec::new();
+            let mut iter = args[1..].iter();
+            while let Some(arg) = iter.next() {
+                match arg.as_str() {
+                    "--pick" => pick = Some(iter.next().and_then(|n| n.parse().ok()).ok_or_else(|| usage.to_string())?),
+                    _ => words.push(arg.clone()),
+                }
+            }
+            let Some(to) = words.iter().position(|word| word == "--to") else {
+                return Err(usage.to_string());
+            };
+            let to_words = &words[to + 1..];
+            let Some(destination) = Destination::parse(to_words) else {
+                let is_bag = |word: &String| word.eq_ignore_ascii_case("bag") || word.eq_ignore_ascii_case("inventory");
+                if to_words.iter().any(is_bag) {
+                    return Err(format!("Only stash tabs can take items, not bags or the inventory.\n{usage}"));
+                }
+                return Err(usage.to_string());
+            };
+            let query = Query::parse(&words[..to])?;
+            if query.is_empty() || pick == Some(0) {
+                return Err(usage.to_string());
+            }
+            Ok(Command::Move(query, destination, pick))
+        }
+        Some("backup") => {
+            let usage = "Usage: gdlc backup list\n       gdlc backup restore <id> [file] [--force]";
+            let force = args.iter().any(|arg| arg == "--force");
+            let rest: Vec<&String> = args.iter().filter(|arg| *arg != "--force").collect();
+            match (rest.get(1).map(|arg| arg.as_str()), rest.get(2)) {
+                (Some("list"), None) if !force => Ok(Command::BackupList),
+                (Some("restore"), Some(id)) if rest.len() <= 4 => {
+                    Ok(Command::BackupRestore(id.to_string(), rest.get(3).map(|file| file.to_string()), force))
+                }
+                _ => Err(usage.to_string()),
+            }
+        }
+        Some("db") => match args.get(1).map(String::as_str) {
+            Some("dump") => {
+                if args.len() > 3 {
+                    return Err("Usage: gdlc db dump [output dir]".to_string());
+                }
+                Ok(Command::DbDump(args.get(2).map(PathBuf::from).unwrap_or_else(|| PathBuf::from("."))))
+            }
+            _ => Err("Usage: gdlc db dump [output dir]".to_string()),
+        },
+        Some("arc") => {
+            let usage = "Usage: gdlc arc list <archive>\n       gdlc arc extract <archive> [file]";
+            match (args.get(1).map(String::as_str), args.get(2)) {
+                (Some("list"), Some(archive)) if args.len() == 3 => Ok(Command::ArcList(PathBuf::from(archive))),
+                (Some("extract"), Some(archive)) if args.len() <= 4 => {
+                    Ok(Command::ArcExtract(PathBuf::from(archive), args.get(3).cloned()))
+                }
+                _ => Err(usage.to_string()),
+            }
+        }
+        _ => parse_search(args),
+    }
+}
+
+fn parse_search(args: &[String]) -> Result<Command, String> {
+    let usage = "Usage: gdlc [query] [--character NAME] [--exclude-character NAME] [--hardcore|--softcore] \
+        [--only equipped|inventory|stash|shared] [--sort location|name|rarity|level] [--reverse] [--group|--group-base] \
+        [--fuzzy] [--format table|json|ndjson|csv]";
+    let mut options = SearchOptions::default();
+    let (filters, sort) = (&mut options.filters, &mut options.sort);
+    let mut words = Vec::new();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--character" => filters.characters.push(iter.next().ok_or(usage)?.to_lowercase()),
+            "--exclude-character" => filters.excluded_characters.push(iter.next().ok_or(usage)?.to_lowercase()),
+            "--hardcore" => filters.hardcore = Some(true),
+            "--softcore" => filters.hardcore = Some(false),
+            // Either repeated or comma separated, eg. --only equipped,inventory
+            "--only" => {
+                for kind in iter.next().ok_or(usage)?.split(',') {
+                    filters.only.push(ContainerKind::parse(&kind.to_lowercase()).ok_or(usage)?);
+                }
+            }
+            "--sort" => sort.key = SortKey::parse(&iter.next().ok_or(usage)?.to_lowercase()).ok_or(usage)?,
+            "--reverse" => sort.reverse = true,
+            "--group" => options.group = Some(Grouping::Item),
+            "--group-base" => options.group = Some(Grouping::Base),
+            "--fuzzy" => options.fuzzy = true,
+            _ => words.push(arg.clone()),
+        }
+    }
+    let query = Query::parse(&words)?;
+    let query = if options.fuzzy { query.fuzzy() } else { query };
+    Ok(Command::Search(query, options))
+}
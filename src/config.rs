@@ -7,6 +7,12 @@ pub struct Config {
     map: HashMap<String, String>,
 }
 
+impl Default for Config {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Config {
     pub fn new() -> Self {
         let mut map = HashMap::new();
@@ -26,10 +32,11 @@ impl Config {
             let mut buf = String::new();
             file.read_to_string(&mut buf).unwrap();
             for line in buf.lines() {
-                if let Some((key, value)) = line.split_once('=') {
-                    if !key.is_empty() && !value.is_empty() {
-                        map.insert(key.trim().to_string(), value.trim().to_string());
-                    }
+                if let Some((key, value)) = line.split_once('=')
+                    && !key.is_empty()
+                    && !value.is_empty()
+                {
+                    map.insert(key.trim().to_string(), value.trim().to_string());
                 }
             }
         }
@@ -46,6 +53,39 @@ impl Config {
         self.map.get("save_dir").map(PathBuf::from)
     }
 
+    /// Where the parsed game database and localization strings are cached between runs.
+    pub fn cache_file(&self) -> Option<PathBuf> {
+        #[cfg(target_os = "windows")]
+        let cache_dir = std::env::var_os("LOCALAPPDATA").map(PathBuf::from);
+        #[cfg(not(target_os = "windows"))]
+        let cache_dir = std::env::var_os("XDG_CACHE_HOME")
+            .map(PathBuf::from)
+            .filter(|dir| dir.is_absolute())
+            .or_else(|| std::env::home_dir().map(|home| home.join(".cache")));
+        cache_dir.map(|dir| dir.join("gdlc").join("game_data.cache"))
+    }
+
+    /// Where save files are backed up before gdlc changes them. Can be set with "backup_dir=", defaults to
+    /// ~/.local/share/gdlc/backups ($XDG_DATA_HOME if set, %LocalAppData% on Windows).
+    pub fn backup_dir(&self) -> Option<PathBuf> {
+        if let Some(dir) = self.map.get("backup_dir") {
+            return Some(PathBuf::from(dir));
+        }
+        #[cfg(target_os = "windows")]
+        let data_dir = std::env::var_os("LOCALAPPDATA").map(PathBuf::from);
+        #[cfg(not(target_os = "windows"))]
+        let data_dir = std::env::var_os("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .filter(|dir| dir.is_absolute())
+            .or_else(|| std::env::home_dir().map(|home| home.join(".local/share")));
+        data_dir.map(|dir| dir.join("gdlc").join("backups"))
+    }
+
+    /// How many backups to keep, "backup_count=". The oldest ones are deleted.
+    pub fn backup_count(&self) -> usize {
+        self.map.get("backup_count").and_then(|count| count.parse().ok()).unwrap_or(20)
+    }
+
     pub fn get_save_files(&self) -> Vec<PathBuf> {
         let mut ret = Vec::new();
         if self.save_dir().is_none() {
@@ -62,6 +102,8 @@ impl Config {
                 }
             });
         }
+        // Directory listings come in no particular order
+        ret.sort();
         ret
     }
 
@@ -73,7 +115,7 @@ impl Config {
         let softcore_stash = save_dir.join("transfer.gst");
         let hardcore_stash = save_dir.join("transfer.gsh");
 
-        (softcore_stash.exists().then(|| softcore_stash), hardcore_stash.exists().then(|| hardcore_stash))
+        (softcore_stash.exists().then_some(softcore_stash), hardcore_stash.exists().then_some(hardcore_stash))
     }
 
     pub fn get_databases(&self) -> Vec<PathBuf> {
@@ -102,6 +144,20 @@ impl Config {
         ];
         return_valid_paths(&paths)
     }
+
+    /// The archives with the item bitmaps, which tell how many inventory cells an item takes.
+    pub fn get_item_texture_archives(&self) -> Vec<PathBuf> {
+        if self.installation_dir().is_none() {
+            return Vec::new();
+        }
+        let install_dir = self.installation_dir().unwrap();
+        let paths = [
+            install_dir.clone().join("resources/Items.arc"),
+            install_dir.clone().join("gdx1/resources/Items.arc"),
+            install_dir.clone().join("gdx2/resources/Items.arc"),
+        ];
+        return_valid_paths(&paths)
+    }
 }
 
 fn return_valid_paths(paths: &[PathBuf]) -> Vec<PathBuf> {
@@ -0,0 +1,80 @@
+use crate::arz_parser::ArzArchive;
+use crate::arz_parser::DbRecord;
+use crate::arz_parser::EntryType;
+use crate::config::Config;
+use crate::error::GdError;
+
+use std::collections::HashSet;
+use std::path::PathBuf;
+
+/// The game database spread over the base game and expansion archives. When a record exists in several archives,
+/// the one from the latest expansion wins, same as in game.
+pub struct Database {
+    archives: Vec<ArzArchive>,
+}
+
+impl Database {
+    /// Archives are expected in load order, base game first.
+    pub fn open(paths: &[PathBuf]) -> Result<Self, GdError> {
+        let mut archives = Vec::with_capacity(paths.len());
+        for path in paths {
+            archives.push(ArzArchive::open(path)?);
+        }
+        Ok(Self { archives })
+    }
+
+    pub fn from_config(config: &Config) -> Result<Self, GdError> {
+        Self::open(&config.get_databases())
+    }
+
+    /// Archives are expected in load order, base game first.
+    pub fn from_archives(archives: Vec<ArzArchive>) -> Self {
+        Self { archives }
+    }
+
+    pub fn archives(&self) -> &[ArzArchive] {
+        &self.archives
+    }
+
+    pub fn record(&self, record_name: &str) -> Result<Option<DbRecord>, GdError> {
+        for archive in self.archives.iter().rev() {
+            if let Some(record) = archive.record(record_name)? {
+                return Ok(Some(record));
+            }
+        }
+        Ok(None)
+    }
+
+    /// The name and rarity of an item or affix, from the latest archive that has the record.
+    pub fn tag_entry(&self, record_name: &str) -> Result<Option<EntryType>, GdError> {
+        match self.archives.iter().rev().find(|archive| archive.contains(record_name)) {
+            Some(archive) => archive.tag_entry(record_name),
+            None => Ok(None),
+        }
+    }
+
+    pub fn contains(&self, record_name: &str) -> bool {
+        self.archives.iter().any(|archive| archive.contains(record_name))
+    }
+
+    /// Every item record across all archives, without duplicates.
+    pub fn item_record_names(&self) -> Vec<&str> {
+        let mut seen = HashSet::new();
+        let archive_names = self.archives.iter().flat_map(ArzArchive::item_record_names);
+        archive_names.filter(|name| seen.insert(*name)).collect()
+    }
+
+    /// Every record name across all archives, without duplicates.
+    pub fn record_names(&self) -> Vec<&str> {
+        let mut seen = HashSet::new();
+        let mut names = Vec::new();
+        for archive in &self.archives {
+            for name in archive.record_names() {
+                if seen.insert(name) {
+                    names.push(name);
+                }
+            }
+        }
+        names
+    }
+}
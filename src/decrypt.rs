@@ -1,94 +1,197 @@
 use crate::byte_reader::ByteReader;
+use crate::error::GdError;
 
-use std::fs::File;
-use std::io::Error;
-use std::io::Read;
 use std::path::PathBuf;
 
 const PRIME: u32 = 39916801;
 
 pub struct Block {
-    #[allow(dead_code)]
+    pub id: u32,
     pub len: u32,
     pub end: u32,
 }
 
+/// A block that isn't decoded, kept as read so that it can be written back unchanged. The data is decrypted byte by
+/// byte, so any ints in it aren't meaningful.
+#[derive(Clone, Debug)]
+pub struct RawBlock {
+    pub id: u32,
+    pub data: Vec<u8>,
+}
+
+/// The table the rolling key is updated from, derived from the file's initial key.
+pub(crate) fn key_table(key: u32) -> [u32; 256] {
+    let mut k = key;
+    let mut table = [0; 256];
+    for i in &mut table {
+        k = k.rotate_right(1).wrapping_mul(PRIME);
+        *i = k;
+    }
+    table
+}
+
 pub struct Decrypt {
     slice_reader: ByteReader,
     table: [u32; 256],
+    initial_key: u32,
     key: u32,
+    blocks: Vec<u32>, // ids of the blocks being read, innermost last
 }
 
 impl Decrypt {
-    pub fn new(path: &PathBuf) -> Result<Self, Error> {
-        let mut file = File::open(path)?;
-        let mut bytes = Vec::new();
-        let _len = file.read_to_end(&mut bytes)?;
-        let mut reader = ByteReader::from_vec(bytes);
-        let key = reader.read_u32() ^ 0x55555555;
-        let mut k = key;
-        let mut table = [0; 256];
-        for i in &mut table {
-            k = k.rotate_right(1).wrapping_mul(PRIME);
-            *i = k;
-        }
+    pub fn new(path: &PathBuf) -> Result<Self, GdError> {
+        Self::from_reader(ByteReader::from_file(path)?)
+    }
 
+    /// Decrypt data that is already in memory.
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<Self, GdError> {
+        Self::from_reader(ByteReader::from_vec(bytes))
+    }
+
+    fn from_reader(mut reader: ByteReader) -> Result<Self, GdError> {
+        let key = reader.read_u32()? ^ 0x55555555;
         Ok(Self {
             slice_reader: reader,
-            table,
+            table: key_table(key),
+            initial_key: key,
             key,
+            blocks: Vec::new(),
         })
     }
 
-    pub fn read_int(&mut self) -> u32 {
-        let num = self.slice_reader.read_u32();
+    pub fn path(&self) -> &PathBuf {
+        self.slice_reader.path()
+    }
+
+    /// The key the file was encrypted with. Writing with the same key reproduces the file byte for byte.
+    pub fn initial_key(&self) -> u32 {
+        self.initial_key
+    }
+
+    fn current_block(&self) -> Option<u32> {
+        self.blocks.last().copied()
+    }
+
+    /// Offset of the next value in the file.
+    pub fn position(&self) -> usize {
+        self.slice_reader.position()
+    }
+
+    /// How many bytes are left in the file.
+    pub fn remaining(&self) -> usize {
+        self.slice_reader.remaining()
+    }
+
+    /// See ByteReader::capacity_for().
+    pub fn capacity_for(&self, count: u32, min_size: usize) -> usize {
+        self.slice_reader.capacity_for(count, min_size)
+    }
+
+    /// An error for a value at the given offset that isn't what the format says it should be.
+    pub fn unexpected_value(&self, offset: usize, field: &'static str, expected: impl ToString, found: impl ToString) -> GdError {
+        GdError::UnexpectedValue {
+            path: self.path().clone(),
+            offset,
+            block: self.current_block(),
+            field,
+            expected: expected.to_string(),
+            found: found.to_string(),
+        }
+    }
+
+    fn invalid_string(&self, offset: usize) -> GdError {
+        GdError::InvalidString {
+            path: self.path().clone(),
+            offset,
+            block: self.current_block(),
+        }
+    }
+
+    /// Read an int that has to have a specific value, eg. a version number.
+    pub fn expect_int(&mut self, field: &'static str, expected: u32) -> Result<u32, GdError> {
+        let offset = self.slice_reader.index;
+        let found = self.read_int()?;
+        if found != expected {
+            return Err(self.unexpected_value(offset, field, expected, found));
+        }
+        Ok(found)
+    }
+
+    /// Same as expect_int(), but for values that don't advance the key.
+    pub fn expect_next_int(&mut self, field: &'static str, expected: u32) -> Result<(), GdError> {
+        let offset = self.slice_reader.index;
+        let found = self.next_int()?;
+        if found != expected {
+            return Err(self.unexpected_value(offset, field, expected, found));
+        }
+        Ok(())
+    }
+
+    pub fn read_int(&mut self) -> Result<u32, GdError> {
+        let block = self.current_block();
+        let num = self.slice_reader.read_u32().map_err(|e| e.with_block(block))?;
         let ret = num ^ self.key;
         for byte in num.to_be_bytes() {
             self.key ^= self.table[byte as usize];
         }
-        ret
+        Ok(ret)
+    }
+
+    pub fn next_int(&mut self) -> Result<u32, GdError> {
+        let block = self.current_block();
+        Ok(self.slice_reader.read_u32().map_err(|e| e.with_block(block))? ^ self.key)
     }
 
-    pub fn next_int(&mut self) -> u32 {
-        self.slice_reader.read_u32() ^ self.key
+    pub fn read_float(&mut self) -> Result<f32, GdError> {
+        Ok(f32::from_bits(self.read_int()?))
     }
 
     #[allow(dead_code)]
-    fn next_float(&mut self) -> f32 {
-        self.next_int() as f32
+    fn next_float(&mut self) -> Result<f32, GdError> {
+        Ok(self.next_int()? as f32)
     }
 
-    pub fn read_byte(&mut self) -> u8 {
-        let byte = self.slice_reader.read_byte();
+    pub fn read_byte(&mut self) -> Result<u8, GdError> {
+        let block = self.current_block();
+        let byte = self.slice_reader.read_byte().map_err(|e| e.with_block(block))?;
+        let ret = byte ^ (self.key as u8);
         self.key ^= self.table[byte as usize];
-        byte ^ (self.key as u8)
+        Ok(ret)
     }
 
-    pub fn read_bool(&mut self) -> bool {
-        self.read_byte() != 0
+    pub fn read_bool(&mut self) -> Result<bool, GdError> {
+        Ok(self.read_byte()? != 0)
     }
 
-    pub fn read_str(&mut self) -> Result<String, Error> {
-        let len = self.read_int();
+    fn read_n_bytes(&mut self, n: u32) -> Result<Vec<u8>, GdError> {
+        let block = self.current_block();
+        self.slice_reader.read_n_bytes(n).map_err(|e| e.with_block(block))
+    }
+
+    pub fn read_str(&mut self) -> Result<String, GdError> {
+        let len = self.read_int()?;
         if len > 0 {
-            let mut str_buf = self.slice_reader.read_n_bytes(len);
+            let offset = self.slice_reader.index;
+            let mut str_buf = self.read_n_bytes(len)?;
             for i in 0..len {
                 let byte = (str_buf[i as usize] as u32 ^ self.key) as u8;
                 self.key ^= self.table[str_buf[i as usize] as usize];
                 str_buf[i as usize] = byte;
             }
-            // TODO error handling for invalid strings
-            let ret_str = String::from_utf8(str_buf).unwrap();
+            let ret_str = String::from_utf8(str_buf).map_err(|_| self.invalid_string(offset))?;
             return Ok(ret_str);
         }
         Ok("".to_string())
     }
 
-    pub fn read_wide_string(&mut self) -> Result<String, Error> {
-        let len_u16 = self.read_int();
+    pub fn read_wide_string(&mut self) -> Result<String, GdError> {
+        let len_u16 = self.read_int()?;
         if len_u16 > 0 {
-            let len_u8 = len_u16 * 2;
-            let mut str_buf = self.slice_reader.read_n_bytes(len_u8);
+            let offset = self.slice_reader.index;
+            let Some(len_u8) = len_u16.checked_mul(2) else {
+                return Err(self.invalid_string(offset));
+            };
+            let mut str_buf = self.read_n_bytes(len_u8)?;
 
             for i in 0..len_u8 {
                 let byte = (str_buf[i as usize] as u32 ^ self.key) as u8;
@@ -108,35 +211,50 @@ impl Decrypt {
                 k += 1;
             }
 
-            // TODO error handling for invalid strings
-            let ret_str = String::from_utf16(&wstr_buf).unwrap();
+            let ret_str = String::from_utf16(&wstr_buf).map_err(|_| self.invalid_string(offset))?;
             return Ok(ret_str);
         }
         Ok("".to_string())
     }
 
-    pub fn read_block_start(&mut self) -> (u32, Block) {
-        let block_start = self.read_int();
-        let len = self.next_int();
-        let index: u32 = self.slice_reader.index.try_into().unwrap();
-        let end = index + len;
-        (block_start, Block { len, end })
-    }
-
-    pub fn read_block_end(&mut self, block: &Block) -> Result<bool, ()> {
-        let stream_pos: u32 = self.slice_reader.index.try_into().unwrap();
-        if block.end != stream_pos {
-            println!(
-                "Stream position is {stream_pos} but block end is {}. Delta: {}",
-                block.end,
-                stream_pos.abs_diff(block.end)
-            );
-            Err(())
-        } else if self.next_int() != 0 {
-            println!("Expected end of block character 0.");
-            Ok(false)
-        } else {
-            Ok(true)
+    pub fn read_block_start(&mut self, expected_id: u32) -> Result<Block, GdError> {
+        let offset = self.slice_reader.index;
+        let id = self.read_int()?;
+        if id != expected_id {
+            return Err(self.unexpected_value(offset, "block id", expected_id, id));
+        }
+        self.blocks.push(id);
+        let len = self.next_int()?;
+        let index = self.slice_reader.index;
+        let end = index.checked_add(len as usize).filter(|&end| end <= self.slice_reader.bytes.len());
+        let Some(end) = end.and_then(|end| u32::try_from(end).ok()) else {
+            return Err(self.unexpected_value(index - 4, "block length", format!("at most {}", self.remaining()), len));
+        };
+        Ok(Block { id, len, end })
+    }
+
+    /// Read a whole block without decoding it, whatever its id.
+    pub fn read_raw_block(&mut self) -> Result<RawBlock, GdError> {
+        // Peek at the id, next_int() leaves the key alone
+        let offset = self.slice_reader.index;
+        let id = self.next_int()?;
+        self.slice_reader.index = offset;
+        let block = self.read_block_start(id)?;
+        let mut data = Vec::with_capacity(block.len as usize);
+        while self.slice_reader.index < block.end as usize {
+            data.push(self.read_byte()?);
+        }
+        self.read_block_end(&block)?;
+        Ok(RawBlock { id, data })
+    }
+
+    pub fn read_block_end(&mut self, block: &Block) -> Result<(), GdError> {
+        let stream_pos = self.slice_reader.index;
+        if block.end as usize != stream_pos {
+            return Err(self.unexpected_value(stream_pos, "end of block position", block.end, stream_pos));
         }
+        self.expect_next_int("end of block marker", 0)?;
+        self.blocks.pop();
+        Ok(())
     }
 }
@@ -0,0 +1,112 @@
+use crate::decrypt::RawBlock;
+use crate::decrypt::key_table;
+use crate::error::GdError;
+
+use std::fs;
+use std::path::Path;
+
+/// A block that has been started but not ended. The length is written when the block ends.
+#[must_use = "blocks need to be ended with write_block_end()"]
+pub struct BlockStart {
+    len_offset: usize,
+    len_key: u32,
+}
+
+/// The writing half of Decrypt. Every write_* undoes the matching read_*, so writing what was read with the same
+/// initial key produces the same file.
+pub struct Encrypt {
+    bytes: Vec<u8>,
+    table: [u32; 256],
+    key: u32,
+}
+
+impl Encrypt {
+    pub fn new(initial_key: u32) -> Self {
+        Self {
+            bytes: (initial_key ^ 0x55555555).to_le_bytes().to_vec(),
+            table: key_table(initial_key),
+            key: initial_key,
+        }
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+
+    pub fn write_int(&mut self, value: u32) {
+        let encrypted = value ^ self.key;
+        for byte in encrypted.to_be_bytes() {
+            self.key ^= self.table[byte as usize];
+        }
+        self.bytes.extend_from_slice(&encrypted.to_le_bytes());
+    }
+
+    /// Counterpart of Decrypt::next_int(), doesn't update the key.
+    pub fn write_next_int(&mut self, value: u32) {
+        self.bytes.extend_from_slice(&(value ^ self.key).to_le_bytes());
+    }
+
+    pub fn write_float(&mut self, value: f32) {
+        self.write_int(value.to_bits());
+    }
+
+    pub fn write_byte(&mut self, value: u8) {
+        let encrypted = value ^ (self.key as u8);
+        self.key ^= self.table[encrypted as usize];
+        self.bytes.push(encrypted);
+    }
+
+    pub fn write_bool(&mut self, value: bool) {
+        self.write_byte(value as u8);
+    }
+
+    pub fn write_str(&mut self, string: &str) {
+        self.write_int(string.len() as u32);
+        for &byte in string.as_bytes() {
+            self.write_byte(byte);
+        }
+    }
+
+    pub fn write_wide_string(&mut self, string: &str) {
+        let wide: Vec<u16> = string.encode_utf16().collect();
+        self.write_int(wide.len() as u32);
+        for wchar in wide {
+            for byte in wchar.to_le_bytes() {
+                self.write_byte(byte);
+            }
+        }
+    }
+
+    pub fn write_block_start(&mut self, id: u32) -> BlockStart {
+        self.write_int(id);
+        let block = BlockStart {
+            len_offset: self.bytes.len(),
+            len_key: self.key,
+        };
+        self.write_next_int(0); // filled in by write_block_end()
+        block
+    }
+
+    pub fn write_block_end(&mut self, block: BlockStart) {
+        let len = (self.bytes.len() - block.len_offset - 4) as u32;
+        let len_bytes = (len ^ block.len_key).to_le_bytes();
+        self.bytes[block.len_offset..block.len_offset + 4].copy_from_slice(&len_bytes);
+        self.write_next_int(0);
+    }
+
+    pub fn write_raw_block(&mut self, raw_block: &RawBlock) {
+        let block = self.write_block_start(raw_block.id);
+        for &byte in &raw_block.data {
+            self.write_byte(byte);
+        }
+        self.write_block_end(block);
+    }
+}
+
+/// Replace a file, eg. a save file. The data is written next to the old file and swapped in, so that nothing reading
+/// it, like the game, ever sees half a file.
+pub fn replace_file(path: &Path, bytes: &[u8]) -> Result<(), GdError> {
+    let tmp_path = path.with_extension(format!("tmp{}", std::process::id()));
+    fs::write(&tmp_path, bytes).map_err(|e| GdError::io(&tmp_path, e))?;
+    fs::rename(&tmp_path, path).map_err(|e| GdError::io(path, e))
+}
@@ -0,0 +1,153 @@
+use std::fmt;
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// Errors from reading the game's save, database and archive files. Offsets are byte offsets into the file, or into
+/// the decompressed data of a database record. Block is the id of the save file block being read, if any.
+#[derive(Debug)]
+pub enum GdError {
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+    UnexpectedEof {
+        path: PathBuf,
+        offset: usize,
+        block: Option<u32>,
+        needed: usize,
+    },
+    UnexpectedValue {
+        path: PathBuf,
+        offset: usize,
+        block: Option<u32>,
+        field: &'static str,
+        expected: String,
+        found: String,
+    },
+    InvalidString {
+        path: PathBuf,
+        offset: usize,
+        block: Option<u32>,
+    },
+    Decompress {
+        path: PathBuf,
+        offset: usize,
+        reason: String,
+    },
+    /// Something went wrong while decoding a database record.
+    Record {
+        record_name: String,
+        source: Box<GdError>,
+    },
+}
+
+impl GdError {
+    pub fn io(path: &Path, source: io::Error) -> Self {
+        Self::Io {
+            path: path.to_path_buf(),
+            source,
+        }
+    }
+
+    /// The file the error happened in. Empty if the data didn't come from a file.
+    pub fn path(&self) -> &Path {
+        match self {
+            Self::Io { path, .. }
+            | Self::UnexpectedEof { path, .. }
+            | Self::UnexpectedValue { path, .. }
+            | Self::InvalidString { path, .. }
+            | Self::Decompress { path, .. } => path,
+            Self::Record { source, .. } => source.path(),
+        }
+    }
+
+    /// Fill in the file path for errors from readers that only had the bytes, eg. decompressed records.
+    pub fn with_path(mut self, file_path: &Path) -> Self {
+        let path = self.path_mut();
+        if path.as_os_str().is_empty() {
+            *path = file_path.to_path_buf();
+        }
+        self
+    }
+
+    fn path_mut(&mut self) -> &mut PathBuf {
+        match self {
+            Self::Io { path, .. }
+            | Self::UnexpectedEof { path, .. }
+            | Self::UnexpectedValue { path, .. }
+            | Self::InvalidString { path, .. }
+            | Self::Decompress { path, .. } => path,
+            Self::Record { source, .. } => source.path_mut(),
+        }
+    }
+
+    /// Fill in the save file block for errors from the underlying byte reader.
+    pub fn with_block(mut self, current_block: Option<u32>) -> Self {
+        if let Self::UnexpectedEof { block, .. } | Self::UnexpectedValue { block, .. } | Self::InvalidString { block, .. } =
+            &mut self
+            && block.is_none()
+        {
+            *block = current_block;
+        }
+        self
+    }
+}
+
+fn fmt_location(f: &mut fmt::Formatter<'_>, path: &Path, offset: usize, block: &Option<u32>) -> fmt::Result {
+    if !path.as_os_str().is_empty() {
+        write!(f, "{}: ", path.display())?;
+    }
+    write!(f, "offset {offset}")?;
+    if let Some(block) = block {
+        write!(f, " (block {block})")?;
+    }
+    write!(f, ": ")
+}
+
+impl fmt::Display for GdError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io { path, source } => write!(f, "{}: {source}", path.display()),
+            Self::UnexpectedEof {
+                path,
+                offset,
+                block,
+                needed,
+            } => {
+                fmt_location(f, path, *offset, block)?;
+                write!(f, "unexpected end of data, needed {needed} more bytes")
+            }
+            Self::UnexpectedValue {
+                path,
+                offset,
+                block,
+                field,
+                expected,
+                found,
+            } => {
+                fmt_location(f, path, *offset, block)?;
+                write!(f, "expected {field} to be {expected}, found {found}")
+            }
+            Self::InvalidString { path, offset, block } => {
+                fmt_location(f, path, *offset, block)?;
+                write!(f, "invalid string")
+            }
+            Self::Decompress { path, offset, reason } => {
+                fmt_location(f, path, *offset, &None)?;
+                write!(f, "decompression failed: {reason}")
+            }
+            Self::Record { record_name, source } => write!(f, "{source} (in record {record_name})"),
+        }
+    }
+}
+
+impl std::error::Error for GdError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io { source, .. } => Some(source),
+            Self::Record { source, .. } => Some(source.as_ref()),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,146 @@
+/// How many typos a search word may have and still match, more for longer words.
+pub fn allowed_typos(word: &str) -> usize {
+    match word.chars().count() {
+        0..=2 => 0,
+        3..=5 => 1,
+        _ => 2,
+    }
+}
+
+/// Edit distance where swapping two neighbouring letters also counts as one typo. Case sensitive, callers lower
+/// case both sides.
+pub fn distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    // Three rows are enough: the current one, and the two before for swaps
+    let mut before_previous = vec![0; b.len() + 1];
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+    for i in 1..=a.len() {
+        current[0] = i;
+        for j in 1..=b.len() {
+            let substitution = previous[j - 1] + usize::from(a[i - 1] != b[j - 1]);
+            current[j] = substitution.min(previous[j] + 1).min(current[j - 1] + 1);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                current[j] = current[j].min(before_previous[j - 2] + 1);
+            }
+        }
+        before_previous.clone_from(&previous);
+        previous.clone_from(&current);
+    }
+    previous[b.len()]
+}
+
+/// How far a lower case search word is from the closest word of the text. A word the text contains, or that starts
+/// one of its words, is 0 away.
+pub fn word_distance(word: &str, text: &str) -> usize {
+    let text = text.to_lowercase();
+    if text.contains(word) {
+        return 0;
+    }
+    let length = word.chars().count();
+    text.split(|c: char| !c.is_alphanumeric() && c != '\'')
+        .filter(|token| !token.is_empty())
+        .map(|token| {
+            // Typos in the part of a longer word that was typed so far, eg. "kraek" for "kraken"
+            let start: String = token.chars().take(length).collect();
+            distance(word, token).min(distance(word, &start))
+        })
+        .min()
+        .unwrap_or(length)
+}
+
+/// The total typos of the search words in the text, None if a word has more than it's allowed.
+pub fn score(words: &[String], text: &str) -> Option<u32> {
+    words.iter().try_fold(0, |total, word| {
+        let typos = word_distance(word, text);
+        (typos <= allowed_typos(word)).then_some(total + typos as u32)
+    })
+}
+
+/// The names closest to the search words, best first, for suggesting when nothing matched. Names that have too
+/// little in common with the search are left out.
+pub fn closest<'a>(words: &[String], names: impl Iterator<Item = &'a str>, count: usize) -> Vec<&'a str> {
+    if words.is_empty() {
+        return Vec::new();
+    }
+    // Twice the typos a match may have, a suggestion only needs to be in the right direction
+    let limit: usize = words.iter().map(|word| 2 * allowed_typos(word).max(1)).sum();
+    let mut scored: Vec<(usize, &str)> = names
+        .filter(|name| !name.is_empty())
+        .map(|name| (words.iter().map(|word| word_distance(word, name)).sum(), name))
+        .filter(|(typos, _)| *typos <= limit)
+        .collect();
+    scored.sort_unstable();
+    scored.dedup_by(|(_, a), (_, b)| a == b);
+    scored.into_iter().take(count).map(|(_, name)| name).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(text: &str) -> Vec<String> {
+        text.split_whitespace().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn typos_allowed() {
+        assert_eq!(allowed_typos("of"), 0);
+        assert_eq!(allowed_typos("ring"), 1);
+        assert_eq!(allowed_typos("kraken"), 2);
+    }
+
+    #[test]
+    fn edit_distance() {
+        assert_eq!(distance("", ""), 0);
+        assert_eq!(distance("ring", ""), 4);
+        assert_eq!(distance("", "ring"), 4);
+        assert_eq!(distance("ring", "ring"), 0);
+        assert_eq!(distance("kitten", "sitting"), 3);
+        // A swap of two neighbouring letters is one typo, not two
+        assert_eq!(distance("rign", "ring"), 1);
+        assert_eq!(distance("ab", "ba"), 1);
+        assert_eq!(distance("kraken", "karekn"), 2);
+        // Case sensitive
+        assert_eq!(distance("Ring", "ring"), 1);
+        assert_eq!(distance("mäch", "mach"), 1);
+    }
+
+    #[test]
+    fn distance_to_a_text() {
+        assert_eq!(word_distance("kraken", "Mythical Ring of the Kraken"), 0);
+        assert_eq!(word_distance("krak", "Mythical Ring of the Kraken"), 0);
+        assert_eq!(word_distance("krakn", "Mythical Ring of the Kraken"), 1);
+        // Typos in the start of a longer word
+        assert_eq!(word_distance("kraek", "Kraken's Tooth"), 1);
+        assert_eq!(word_distance("kraken's", "Kraken's Tooth"), 0);
+        assert_eq!(word_distance("rign", "Ring of Valor"), 1);
+        assert_eq!(word_distance("xyz", ""), 3);
+    }
+
+    #[test]
+    fn scores() {
+        assert_eq!(score(&words("ring kraken"), "Ring of the Kraken"), Some(0));
+        assert_eq!(score(&words("rign krakne"), "Ring of the Kraken"), Some(2));
+        assert_eq!(score(&words("ring zzzzzz"), "Ring of the Kraken"), None);
+        // Too short for a typo
+        assert_eq!(score(&words("og"), "Ring of the Kraken"), None);
+        assert_eq!(score(&[], "Ring of the Kraken"), Some(0));
+    }
+
+    #[test]
+    fn closest_names() {
+        let names = [
+            "Ring of the Kraken",
+            "Kraken's Tooth",
+            "Belt of Valor",
+            "Ring of the Kraken",
+            "",
+        ];
+        assert_eq!(closest(&words("krakn"), names.into_iter(), 5), ["Kraken's Tooth", "Ring of the Kraken"]);
+        assert_eq!(closest(&words("krakn"), names.into_iter(), 1), ["Kraken's Tooth"]);
+        assert!(closest(&words("zzzzzz"), names.into_iter(), 5).is_empty());
+        assert!(closest(&[], names.into_iter(), 5).is_empty());
+    }
+}
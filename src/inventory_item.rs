@@ -1,6 +1,8 @@
-use super::decrypt::Decrypt;
+use crate::decrypt::Decrypt;
+use crate::encrypt::Encrypt;
+use crate::error::GdError;
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 #[allow(dead_code)]
 pub struct InventoryItem {
     pub base_name: String,
@@ -20,22 +22,39 @@ pub struct InventoryItem {
 }
 
 impl InventoryItem {
-    pub fn read(decrypter: &mut Decrypt) -> Result<Self, std::io::Error> {
+    pub fn read(decrypter: &mut Decrypt) -> Result<Self, GdError> {
         Ok(Self {
             base_name: decrypter.read_str()?,
             prefix_name: decrypter.read_str()?,
-            suffix_name:decrypter.read_str()?,
+            suffix_name: decrypter.read_str()?,
             modifier_name: decrypter.read_str()?,
             transmute_name: decrypter.read_str()?,
-            seed: decrypter.read_int(),
+            seed: decrypter.read_int()?,
             component_name: decrypter.read_str()?,
             relic_completion_bonus: decrypter.read_str()?,
-            relic_seed: decrypter.read_int(),
+            relic_seed: decrypter.read_int()?,
             augment_name: decrypter.read_str()?,
-            unknown: decrypter.read_int(),
-            augment_seed: decrypter.read_int(),
-            materia_combines: decrypter.read_int(),
-            stack_count: decrypter.read_int(),
+            unknown: decrypter.read_int()?,
+            augment_seed: decrypter.read_int()?,
+            materia_combines: decrypter.read_int()?,
+            stack_count: decrypter.read_int()?,
         })
     }
+
+    pub fn write(&self, encrypt: &mut Encrypt) {
+        encrypt.write_str(&self.base_name);
+        encrypt.write_str(&self.prefix_name);
+        encrypt.write_str(&self.suffix_name);
+        encrypt.write_str(&self.modifier_name);
+        encrypt.write_str(&self.transmute_name);
+        encrypt.write_int(self.seed);
+        encrypt.write_str(&self.component_name);
+        encrypt.write_str(&self.relic_completion_bonus);
+        encrypt.write_int(self.relic_seed);
+        encrypt.write_str(&self.augment_name);
+        encrypt.write_int(self.unknown);
+        encrypt.write_int(self.augment_seed);
+        encrypt.write_int(self.materia_combines);
+        encrypt.write_int(self.stack_count);
+    }
 }
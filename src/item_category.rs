@@ -0,0 +1,202 @@
+use std::fmt;
+use std::fmt::Display;
+
+/// What kind of item a record is, from its record type, eg. "ArmorProtective_Waist" is a belt.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ItemCategory {
+    Head,
+    Amulet,
+    Chest,
+    Legs,
+    Feet,
+    Hands,
+    Ring,
+    Belt,
+    Shoulders,
+    Medal,
+    Relic,
+    Sword,
+    Axe,
+    Mace,
+    Dagger,
+    Scepter,
+    Spear,
+    Staff,
+    Sword2h,
+    Axe2h,
+    Mace2h,
+    /// Pistols and one handed crossbows
+    Ranged1h,
+    /// Rifles and crossbows
+    Ranged2h,
+    Shield,
+    /// Caster off-hands
+    OffHand,
+    Component,
+    Augment,
+    Potion,
+    /// Elixirs, oils, aether crystals and the like
+    Consumable,
+    Blueprint,
+    QuestItem,
+    FactionBooster,
+    /// Items that grant a skill, eg. runes
+    UsableSkill,
+    #[default]
+    Other,
+}
+
+impl ItemCategory {
+    pub const ALL: [Self; 34] = [
+        Self::Head,
+        Self::Amulet,
+        Self::Chest,
+        Self::Legs,
+        Self::Feet,
+        Self::Hands,
+        Self::Ring,
+        Self::Belt,
+        Self::Shoulders,
+        Self::Medal,
+        Self::Relic,
+        Self::Sword,
+        Self::Axe,
+        Self::Mace,
+        Self::Dagger,
+        Self::Scepter,
+        Self::Spear,
+        Self::Staff,
+        Self::Sword2h,
+        Self::Axe2h,
+        Self::Mace2h,
+        Self::Ranged1h,
+        Self::Ranged2h,
+        Self::Shield,
+        Self::OffHand,
+        Self::Component,
+        Self::Augment,
+        Self::Potion,
+        Self::Consumable,
+        Self::Blueprint,
+        Self::QuestItem,
+        Self::FactionBooster,
+        Self::UsableSkill,
+        Self::Other,
+    ];
+
+    /// The record type is the record's "Class" field, eg. "WeaponMelee_Sword2h".
+    pub fn from_record_type(record_type: &str) -> Self {
+        match record_type {
+            "ArmorProtective_Head" => Self::Head,
+            "ArmorJewelry_Amulet" => Self::Amulet,
+            "ArmorProtective_Chest" => Self::Chest,
+            "ArmorProtective_Legs" => Self::Legs,
+            "ArmorProtective_Feet" => Self::Feet,
+            "ArmorProtective_Hands" => Self::Hands,
+            "ArmorJewelry_Ring" => Self::Ring,
+            "ArmorProtective_Waist" => Self::Belt,
+            "ArmorProtective_Shoulders" => Self::Shoulders,
+            "ArmorJewelry_Medal" => Self::Medal,
+            "ItemArtifact" => Self::Relic,
+            "WeaponMelee_Sword" => Self::Sword,
+            "WeaponMelee_Axe" => Self::Axe,
+            "WeaponMelee_Mace" => Self::Mace,
+            "WeaponMelee_Dagger" => Self::Dagger,
+            "WeaponMelee_Scepter" => Self::Scepter,
+            "WeaponHunting_Spear" => Self::Spear,
+            "WeaponMagical_Staff" => Self::Staff,
+            "WeaponMelee_Sword2h" => Self::Sword2h,
+            "WeaponMelee_Axe2h" => Self::Axe2h,
+            "WeaponMelee_Mace2h" => Self::Mace2h,
+            "WeaponHunting_Ranged1h" => Self::Ranged1h,
+            "WeaponHunting_Ranged2h" => Self::Ranged2h,
+            "WeaponArmor_Shield" => Self::Shield,
+            "WeaponArmor_Offhand" => Self::OffHand,
+            // Components are called relics in the database, and relics artifacts
+            "ItemRelic" => Self::Component,
+            "ItemEnchantment" => Self::Augment,
+            "ItemArtifactFormula" => Self::Blueprint,
+            "QuestItem" => Self::QuestItem,
+            "ItemFactionBooster" => Self::FactionBooster,
+            "ItemUsableSkill" => Self::UsableSkill,
+            _ if record_type.starts_with("OneShot_Potion") => Self::Potion,
+            _ if record_type.starts_with("OneShot") => Self::Consumable,
+            _ => Self::Other,
+        }
+    }
+
+    /// Lower case and without spaces, for filters and scripts, eg. "sword2h" or "quest_item".
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Head => "head",
+            Self::Amulet => "amulet",
+            Self::Chest => "chest",
+            Self::Legs => "legs",
+            Self::Feet => "feet",
+            Self::Hands => "hands",
+            Self::Ring => "ring",
+            Self::Belt => "belt",
+            Self::Shoulders => "shoulders",
+            Self::Medal => "medal",
+            Self::Relic => "relic",
+            Self::Sword => "sword",
+            Self::Axe => "axe",
+            Self::Mace => "mace",
+            Self::Dagger => "dagger",
+            Self::Scepter => "scepter",
+            Self::Spear => "spear",
+            Self::Staff => "staff",
+            Self::Sword2h => "sword2h",
+            Self::Axe2h => "axe2h",
+            Self::Mace2h => "mace2h",
+            Self::Ranged1h => "ranged1h",
+            Self::Ranged2h => "ranged2h",
+            Self::Shield => "shield",
+            Self::OffHand => "off-hand",
+            Self::Component => "component",
+            Self::Augment => "augment",
+            Self::Potion => "potion",
+            Self::Consumable => "consumable",
+            Self::Blueprint => "blueprint",
+            Self::QuestItem => "quest_item",
+            Self::FactionBooster => "faction_booster",
+            Self::UsableSkill => "usable_skill",
+            Self::Other => "other",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|category| category.name() == name)
+    }
+
+    pub fn is_weapon(&self) -> bool {
+        (Self::Sword..=Self::Ranged2h).contains(self)
+    }
+
+    /// Where the item is equipped, eg. "off-hand" for shields too or "weapon" for any weapon. None if it can't be.
+    pub fn slot(&self) -> Option<&'static str> {
+        if self.is_weapon() {
+            Some("weapon")
+        } else if *self == Self::Shield {
+            Some("off-hand")
+        } else if *self <= Self::Relic || *self == Self::OffHand {
+            Some(self.name())
+        } else {
+            None
+        }
+    }
+}
+
+/// Lower case words, eg. "two-handed sword" or "quest item".
+impl Display for ItemCategory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Sword2h => write!(f, "two-handed sword"),
+            Self::Axe2h => write!(f, "two-handed axe"),
+            Self::Mace2h => write!(f, "two-handed mace"),
+            Self::Ranged1h => write!(f, "one-handed ranged weapon"),
+            Self::Ranged2h => write!(f, "two-handed ranged weapon"),
+            _ => write!(f, "{}", self.name().replace('_', " ")),
+        }
+    }
+}
@@ -0,0 +1,141 @@
+use crate::player::EQUIPMENT_SLOT_NAMES;
+use crate::player::WEAPON_SLOT_NAMES;
+
+use std::fmt;
+use std::fmt::Display;
+
+/// Where an item is. Tabs, bags, weapon sets and slots are counted from 0, positions are in grid cells from the top
+/// left.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ItemLocation {
+    SharedStash {
+        hardcore: bool,
+        tab: usize,
+        x: u32,
+        y: u32,
+    },
+    CharacterStash {
+        character: String,
+        tab: usize,
+        x: u32,
+        y: u32,
+    },
+    Bag {
+        character: String,
+        bag: usize,
+        x: u32,
+        y: u32,
+    },
+    Equipped {
+        character: String,
+        slot: usize,
+    },
+    WeaponSet {
+        character: String,
+        set: usize,
+        slot: usize,
+        /// Whether the character has this set in hand
+        active: bool,
+    },
+}
+
+impl ItemLocation {
+    /// The character holding the item, None for the shared stashes.
+    pub fn character(&self) -> Option<&str> {
+        match self {
+            Self::SharedStash { .. } => None,
+            Self::CharacterStash { character, .. }
+            | Self::Bag { character, .. }
+            | Self::Equipped { character, .. }
+            | Self::WeaponSet { character, .. } => Some(character),
+        }
+    }
+
+    /// Position within the stash tab or bag, None for equipped items.
+    pub fn position(&self) -> Option<(u32, u32)> {
+        match *self {
+            Self::SharedStash { x, y, .. } | Self::CharacterStash { x, y, .. } | Self::Bag { x, y, .. } => Some((x, y)),
+            Self::Equipped { .. } | Self::WeaponSet { .. } => None,
+        }
+    }
+
+    pub fn is_equipped(&self) -> bool {
+        matches!(self, Self::Equipped { .. } | Self::WeaponSet { .. })
+    }
+
+    /// Lower case, eg. "shared_stash" or "weapon_set".
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::SharedStash { .. } => "shared_stash",
+            Self::CharacterStash { .. } => "character_stash",
+            Self::Bag { .. } => "bag",
+            Self::Equipped { .. } => "equipped",
+            Self::WeaponSet { .. } => "weapon_set",
+        }
+    }
+
+    /// "Softcore" or "Hardcore" for the shared stashes, otherwise the character's name.
+    pub fn owner(&self) -> &str {
+        match self {
+            Self::SharedStash { hardcore: true, .. } => "Hardcore",
+            Self::SharedStash { hardcore: false, .. } => "Softcore",
+            Self::CharacterStash { character, .. }
+            | Self::Bag { character, .. }
+            | Self::Equipped { character, .. }
+            | Self::WeaponSet { character, .. } => character,
+        }
+    }
+
+    /// The tab, bag or weapon set, None for other equipment.
+    pub fn container_index(&self) -> Option<usize> {
+        match *self {
+            Self::SharedStash { tab, .. } | Self::CharacterStash { tab, .. } => Some(tab),
+            Self::Bag { bag, .. } => Some(bag),
+            Self::WeaponSet { set, .. } => Some(set),
+            Self::Equipped { .. } => None,
+        }
+    }
+
+    /// Eg. "Ring 1" or "Off hand", None unless equipped.
+    pub fn slot_name(&self) -> Option<&'static str> {
+        match *self {
+            Self::Equipped { slot, .. } => Some(EQUIPMENT_SLOT_NAMES.get(slot).copied().unwrap_or("Unknown slot")),
+            Self::WeaponSet { slot, .. } => Some(WEAPON_SLOT_NAMES.get(slot).copied().unwrap_or("Unknown slot")),
+            _ => None,
+        }
+    }
+
+    /// The order results are listed in: shared stashes first, then by character, container, tab or bag and position
+    /// row by row. Not an Ord, as character names compare without case and weapon sets whether active or not.
+    pub fn sort_key(&self) -> (Option<String>, u8, usize, usize, u32, u32) {
+        let character = self.character().map(str::to_lowercase);
+        match *self {
+            Self::SharedStash { hardcore, tab, x, y } => (character, 0, hardcore as usize, tab, y, x),
+            Self::Bag { bag, x, y, .. } => (character, 1, 0, bag, y, x),
+            Self::CharacterStash { tab, x, y, .. } => (character, 2, 0, tab, y, x),
+            Self::Equipped { slot, .. } => (character, 3, 0, slot, 0, 0),
+            Self::WeaponSet { set, slot, .. } => (character, 4, set, slot, 0, 0),
+        }
+    }
+}
+
+/// Without the position, eg. "Softcore stash tab 3", "Alina bag 1" or "Equipped by Alina, ring 2".
+impl Display for ItemLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::SharedStash { tab, .. } => write!(f, "{} stash tab {}", self.owner(), tab + 1),
+            Self::CharacterStash { character, tab, .. } => write!(f, "{character} stash tab {}", tab + 1),
+            Self::Bag { character, bag, .. } => write!(f, "{character} bag {}", bag + 1),
+            Self::Equipped { character, .. } => {
+                write!(f, "Equipped by {character}, {}", self.slot_name().unwrap_or_default().to_lowercase())
+            }
+            Self::WeaponSet {
+                character, set, active, ..
+            } => {
+                let slot_name = self.slot_name().unwrap_or_default().to_lowercase();
+                let inactive = if *active { "" } else { " (inactive)" };
+                write!(f, "Equipped by {character}, weapon set {}{inactive}, {slot_name}", set + 1)
+            }
+        }
+    }
+}
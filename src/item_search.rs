@@ -1,25 +1,158 @@
+use crate::arz_parser::AffixInfo;
 use crate::arz_parser::EntryType;
+use crate::database::Database;
+use crate::error::GdError;
 use crate::inventory_item::InventoryItem;
+use crate::item_category::ItemCategory;
+use crate::worker_pool::WorkerPool;
 
 use std::collections::HashMap;
+use std::collections::HashSet;
+use std::sync::Mutex;
 use std::{fmt, fmt::Display};
 
 use colored::{ColoredString, Colorize};
 
 pub type LocalizationStrings = HashMap<String, String>;
 
-#[derive(Debug, Default)]
+/// Looked up records by record name. None if the record isn't an item or affix.
+pub type TagEntries = HashMap<String, Option<EntryType>>;
+
+/// Record name and item level.
+pub type ItemTier = (String, Option<u32>);
+
+/// Item and affix names from the game database. A record is only decoded the first time it is looked up, and
+/// remembered after that.
+#[derive(Default)]
 pub struct TagNames {
-    pub items: HashMap<String, (EntryType, Vec<Option<u32>>)>,
-    pub affixes: HashMap<String, EntryType>,
+    database: Option<Database>,
+    entries: Mutex<TagEntries>,
+    /// Items by tag name and whether they are upgraded, see index_tags()
+    tiers: HashMap<(String, bool), Vec<ItemTier>>,
+    known_count: usize,
+    /// Records that couldn't be decoded
+    errors: Mutex<Vec<GdError>>,
+}
+
+impl TagNames {
+    /// Start from records that were looked up before, eg. by an earlier run. The database is only needed for the
+    /// rest.
+    pub fn new(entries: TagEntries) -> Self {
+        Self {
+            known_count: entries.len(),
+            entries: Mutex::new(entries),
+            ..Self::default()
+        }
+    }
+
+    pub fn set_database(&mut self, database: Database) {
+        self.database = Some(database);
+    }
+
+    /// The database, if it was needed to look up records.
+    pub fn database(&self) -> Option<&Database> {
+        self.database.as_ref()
+    }
+
+    /// The records that haven't been looked up yet, without duplicates.
+    pub fn unknown<'a>(&self, record_names: impl Iterator<Item = &'a str>) -> Vec<String> {
+        let entries = self.entries.lock().unwrap();
+        let mut seen = HashSet::new();
+        record_names
+            .filter(|name| !name.is_empty() && !entries.contains_key(*name) && seen.insert(*name))
+            .map(str::to_string)
+            .collect()
+    }
+
+    /// Look up many records on the pool's threads at once, instead of one by one as items ask for them.
+    pub fn resolve(&self, record_names: &[String], pool: &WorkerPool) {
+        pool.map(record_names, |name| self.entry(name));
+    }
+
+    /// Look up every item record of the database that isn't known yet, eg. so that index_tags() has them all.
+    pub fn resolve_items(&self, pool: &WorkerPool) {
+        if let Some(database) = &self.database {
+            let unknown_records = self.unknown(database.item_record_names().into_iter());
+            self.resolve(&unknown_records, pool);
+        }
+    }
+
+    pub fn item(&self, record_name: &str) -> Option<EntryType> {
+        self.entry(record_name).filter(|entry| matches!(entry, EntryType::Item(..)))
+    }
+
+    pub fn affix(&self, record_name: &str) -> Option<AffixInfo> {
+        match self.entry(record_name) {
+            Some(EntryType::Affix(affix_info)) => Some(affix_info),
+            _ => None,
+        }
+    }
+
+    fn entry(&self, record_name: &str) -> Option<EntryType> {
+        if let Some(entry) = self.entries.lock().unwrap().get(record_name) {
+            return entry.clone();
+        }
+        let database = self.database.as_ref()?;
+        // The lock isn't held while decoding, so other threads can decode other records meanwhile
+        let entry = match database.tag_entry(record_name) {
+            Ok(entry) => entry,
+            Err(e) => {
+                self.errors.lock().unwrap().push(e);
+                None
+            }
+        };
+        self.entries.lock().unwrap().insert(record_name.to_string(), entry.clone());
+        entry
+    }
+
+    /// Whether records were looked up since this was created, ie. the cache is out of date.
+    pub fn has_new_entries(&self) -> bool {
+        self.entries.lock().unwrap().len() > self.known_count
+    }
+
+    /// False if a record couldn't be decoded. Its entry is None, which shouldn't be cached.
+    pub fn is_complete(&self) -> bool {
+        self.errors.lock().unwrap().is_empty()
+    }
+
+    /// Why records couldn't be decoded, for the caller to report wherever its output allows.
+    pub fn errors(&self) -> Vec<String> {
+        self.errors.lock().unwrap().iter().map(GdError::to_string).collect()
+    }
+
+    pub fn entries(&self) -> TagEntries {
+        self.entries.lock().unwrap().clone()
+    }
+
+    /// Index the items by tag name as well, for tiers(). Needs every item record looked up, see resolve_items().
+    pub fn index_tags(&mut self) {
+        self.tiers.clear();
+        for entry in self.entries.get_mut().unwrap().values() {
+            if let Some(EntryType::Item(record_name, tag_name, _, item_level, _)) = entry {
+                let key = (tag_name.clone(), is_upgraded(record_name));
+                self.tiers.entry(key).or_default().push((record_name.clone(), *item_level));
+            }
+        }
+        for tiers in self.tiers.values_mut() {
+            tiers.sort_by(|(a_record, a_level), (b_record, b_level)| a_level.cmp(b_level).then(a_record.cmp(b_record)));
+        }
+    }
+
+    /// Every tier of an item record, lowest item level first. An item that comes in several tiers, eg. with higher
+    /// stats at higher levels, has one record per tier that all share its tag name. Mythical upgrades share it too,
+    /// but are items of their own. Empty unless index_tags() was called.
+    pub fn tiers(&self, tag_name: &str, record_name: &str) -> &[ItemTier] {
+        let key = (tag_name.to_string(), is_upgraded(record_name));
+        self.tiers.get(&key).map_or(&[], Vec::as_slice)
+    }
 }
 
 pub struct ItemLookup {
-    pub search_term: String,
     pub localization_data: HashMap<String, String>,
     pub tag_names: TagNames,
 }
 
+#[derive(Clone)]
 pub struct CompleteItem {
     name: String,
     item_rarity: Rarity,
@@ -29,37 +162,129 @@ pub struct CompleteItem {
     suffix_rarity: Rarity,
     level_req: Option<u32>,
     quantity: u32,
+    category: ItemCategory,
+    /// Which of the records sharing the item's name this is, and how many there are. None if there's just the one.
+    tier: Option<(usize, usize)>,
+    /// As it is in the save file
+    inventory_item: InventoryItem,
 }
 
-enum Rarity {
-    Legendary,
-    Epic,
-    Rare,
-    RareComponent,
+/// From the lowest to the highest.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Rarity {
+    Broken,
+    Common,
+    /// Quest items, which can't be traded or sold
+    Quest,
+    Component,
     Magical,
-    CommonOrUnknown,
+    /// Components the game names in gold, see item_rarity()
+    RareComponent,
+    Rare,
+    Epic,
+    Legendary,
+    /// Upgraded legendaries, named "Mythical ..." in game, with a better version of a legendary's bonuses
+    Mythical,
 }
 
-impl From<&String> for Rarity {
-    fn from(string: &String) -> Self {
-        match string.to_lowercase().as_str() {
-            "legendary" => Self::Legendary,
+impl Rarity {
+    pub const ALL: [Self; 10] = [
+        Self::Broken,
+        Self::Common,
+        Self::Quest,
+        Self::Component,
+        Self::Magical,
+        Self::RareComponent,
+        Self::Rare,
+        Self::Epic,
+        Self::Legendary,
+        Self::Mythical,
+    ];
+
+    /// From name().
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|rarity| rarity.name() == name)
+    }
+
+    /// An itemClassification value of the database, of items and affixes. Items without one are common.
+    pub fn from_classification(classification: &str) -> Self {
+        match classification.to_lowercase().as_str() {
+            "broken" => Self::Broken,
+            "quest" => Self::Quest,
+            "magical" => Self::Magical,
             "rare" => Self::Rare,
             "epic" => Self::Epic,
-            "magical" => Self::Magical,
-            _ => Self::CommonOrUnknown,
+            "legendary" => Self::Legendary,
+            "mythical" => Self::Mythical,
+            _ => Self::Common,
+        }
+    }
+
+    /// Lower case, eg. "rare_component".
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Broken => "broken",
+            Self::Common => "common",
+            Self::Quest => "quest",
+            Self::Component => "component",
+            Self::Magical => "magical",
+            Self::RareComponent => "rare_component",
+            Self::Rare => "rare",
+            Self::Epic => "epic",
+            Self::Legendary => "legendary",
+            Self::Mythical => "mythical",
+        }
+    }
+}
+
+/// The rarity of an item, which for components, quest items and Mythical legendaries isn't the database's
+/// itemClassification. Mythical legendaries are the upgraded ones, eg. "records/items/upgraded/gearlegs/d008_legs.dbr".
+///
+/// Rare components have the same record fields as the others, the only sign of them is the color code in front of
+/// their name, which is why that's passed along. It's ignored for anything but components.
+fn item_rarity(classification: &str, category: ItemCategory, record_name: &str, color: Option<char>) -> Rarity {
+    match Rarity::from_classification(classification) {
+        Rarity::Common if category == ItemCategory::Component && color == Some('k') => Rarity::RareComponent,
+        Rarity::Common if category == ItemCategory::Component => Rarity::Component,
+        Rarity::Common if category == ItemCategory::QuestItem => Rarity::Quest,
+        Rarity::Legendary if is_upgraded(record_name) => Rarity::Mythical,
+        rarity => rarity,
+    }
+}
+
+/// Whether the record is the upgraded version of an item, which for legendaries is the Mythical one.
+fn is_upgraded(record_name: &str) -> bool {
+    record_name.replace('\\', "/").to_lowercase().starts_with("records/items/upgraded/")
+}
+
+/// Localized text without the game's color codes, eg. "^k" in front of rare component names, and the first color.
+fn strip_color_codes(text: &str) -> (String, Option<char>) {
+    let mut stripped = String::with_capacity(text.len());
+    let mut color = None;
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '^' {
+            let code = chars.next();
+            color = color.or(code);
+        } else {
+            stripped.push(c);
         }
     }
+    (stripped, color)
 }
 
 fn color_item_by_rarity(string: String, rarity: &Rarity) -> ColoredString {
     match rarity {
+        Rarity::Mythical => string.bright_magenta(),
         Rarity::Legendary => string.purple(),
         Rarity::Epic => string.bright_blue(),
         Rarity::Rare => string.bright_green(),
         Rarity::RareComponent => string.yellow(),
         Rarity::Magical => string.bright_yellow(),
-        Rarity::CommonOrUnknown => ColoredString::from(string),
+        Rarity::Component => string.bright_white(),
+        Rarity::Quest => string.cyan(),
+        Rarity::Common => ColoredString::from(string),
+        Rarity::Broken => string.bright_black(),
     }
 }
 
@@ -72,13 +297,77 @@ fn color_affix_by_rarity(string: String, rarity: &Rarity) -> ColoredString {
 }
 
 impl CompleteItem {
-    fn fmt_searchable_item_name(&self) -> String {
-        format!(
-            "{} {} {}", // correct amount of whitespace is not important for search
-            self.prefix.as_ref().unwrap_or(&"".into()),
-            &self.name,
-            self.suffix.as_ref().unwrap_or(&"".into())
-        )
+    /// The base name, without affixes.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn rarity(&self) -> Rarity {
+        self.item_rarity
+    }
+
+    pub fn prefix(&self) -> Option<&str> {
+        self.prefix.as_deref()
+    }
+
+    pub fn prefix_rarity(&self) -> Rarity {
+        self.prefix_rarity
+    }
+
+    pub fn suffix(&self) -> Option<&str> {
+        self.suffix.as_deref()
+    }
+
+    pub fn suffix_rarity(&self) -> Rarity {
+        self.suffix_rarity
+    }
+
+    pub fn level_req(&self) -> Option<u32> {
+        self.level_req
+    }
+
+    pub fn quantity(&self) -> u32 {
+        self.quantity
+    }
+
+    pub fn category(&self) -> ItemCategory {
+        self.category
+    }
+
+    /// Eg. Some((2, 3)) for the second of three tiers, counted from the lowest item level.
+    pub fn tier(&self) -> Option<(usize, usize)> {
+        self.tier
+    }
+
+    /// The record names and seeds the item was made from.
+    pub fn inventory_item(&self) -> &InventoryItem {
+        &self.inventory_item
+    }
+
+    /// Eg. the total of several stacks.
+    pub fn with_quantity(mut self, quantity: u32) -> Self {
+        self.quantity = quantity;
+        self
+    }
+
+    /// Just the base item, eg. to count every ring of a kind whatever its affixes.
+    pub fn without_affixes(mut self) -> Self {
+        self.prefix = None;
+        self.prefix_rarity = Rarity::Common;
+        self.suffix = None;
+        self.suffix_rarity = Rarity::Common;
+        self.inventory_item.prefix_name.clear();
+        self.inventory_item.suffix_name.clear();
+        self
+    }
+
+    /// Prefix, name and suffix, without colors.
+    pub fn full_name(&self) -> String {
+        [self.prefix.as_deref(), Some(self.name.as_str()), self.suffix.as_deref()]
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>()
+            .join(" ")
     }
 }
 
@@ -117,64 +406,76 @@ impl Display for CompleteItem {
 }
 
 impl ItemLookup {
+    /// The text of a tag, without color codes, and the first color it had if any. Every name goes through here.
+    fn localize(&self, tag_name: &str) -> Option<(String, Option<char>)> {
+        self.localization_data.get(tag_name).map(|text| strip_color_codes(text))
+    }
+
+    /// The names of the items and affixes looked up so far, eg. to suggest some when nothing matches. That's every
+    /// item once TagNames::resolve_items() ran. Other text of the localization files is left out.
+    pub fn names(&self) -> Vec<String> {
+        let entries = self.tag_names.entries.lock().unwrap();
+        let mut seen = HashSet::new();
+        let mut names = Vec::new();
+        for entry in entries.values().flatten() {
+            let name = match entry {
+                EntryType::Item(_, tag_name, ..) => self.localize(tag_name).map(|(name, _)| name),
+                EntryType::Affix(affix_info) => affix_info.name.clone().or_else(|| {
+                    let tag_name = affix_info.tag_name.as_ref()?;
+                    self.localize(tag_name).map(|(name, _)| name)
+                }),
+            };
+            if let Some(name) = name
+                && seen.insert(name.clone())
+            {
+                names.push(name);
+            }
+        }
+        names
+    }
+
     pub fn lookup_item(&self, inventory_item: &InventoryItem) -> Option<CompleteItem> {
-        if let Some((EntryType::Item(_record_name, tag_name, item_rarity, level_req), ilvls)) =
-            self.tag_names.items.get(&inventory_item.base_name)
+        if let Some(EntryType::Item(record_name, tag_name, classification, level_req, category)) =
+            self.tag_names.item(&inventory_item.base_name)
         {
-            if let Some(item_name) = self.localization_data.get(tag_name) {
-                // Uncomment to get record name and tag name of an item that the player has
-                //if item_name == "Baldir's Mantle" {
-                //    //println!("mantle is {record_name}, {tag_name}");
-                //    println!("{:?}", inventory_item);
-                //}
-                // TODO fix this logic, hashmap needs to count tagnames and not record names
-                if ilvls.len() > 1 {
-                    println!("{item_name} has {} tiers", ilvls.len());
-                }
+            if let Some((item_name, color)) = self.localize(&tag_name) {
+                let tiers = self.tag_names.tiers(&tag_name, &record_name);
+                let tier = match tiers.iter().position(|(tier_record, _)| *tier_record == record_name) {
+                    Some(index) if tiers.len() > 1 => Some((index + 1, tiers.len())),
+                    _ => None,
+                };
 
                 let mut prefix: Option<String> = None;
-                let mut prefix_rarity = Rarity::CommonOrUnknown;
-                if !inventory_item.prefix_name.is_empty() {
-                    let tag_prefix = self.tag_names.affixes.get(&inventory_item.prefix_name);
-                    if let Some(EntryType::Affix(affix_info)) = tag_prefix {
-                        prefix_rarity = Rarity::from(&affix_info.rarity);
-                        if let Some(affix_name) = &affix_info.name {
-                            prefix = Some(affix_name.clone());
-                        } else if let Some(tag_name) = &affix_info.tag_name {
-                            if let Some(name) = self.localization_data.get(tag_name) {
-                                prefix = Some(name.clone());
-                            }
-                        }
+                let mut prefix_rarity = Rarity::Common;
+                if !inventory_item.prefix_name.is_empty()
+                    && let Some(affix_info) = self.tag_names.affix(&inventory_item.prefix_name)
+                {
+                    prefix_rarity = Rarity::from_classification(&affix_info.rarity);
+                    if let Some(affix_name) = &affix_info.name {
+                        prefix = Some(affix_name.clone());
+                    } else if let Some(tag_name) = &affix_info.tag_name
+                        && let Some((name, _)) = self.localize(tag_name)
+                    {
+                        prefix = Some(name);
                     }
                 }
                 let mut suffix = None;
-                let mut suffix_rarity = Rarity::CommonOrUnknown;
-                if !inventory_item.suffix_name.is_empty() {
-                    let tag_suffix = self.tag_names.affixes.get(&inventory_item.suffix_name);
-                    if let Some(EntryType::Affix(affix_info)) = tag_suffix {
-                        suffix_rarity = Rarity::from(&affix_info.rarity);
-                        if let Some(name) = &affix_info.name {
-                            suffix = Some(name.clone());
-                        } else if let Some(tag_name) = &affix_info.tag_name {
-                            if let Some(name) = self.localization_data.get(tag_name) {
-                                suffix = Some(name.clone());
-                            }
-                        }
+                let mut suffix_rarity = Rarity::Common;
+                if !inventory_item.suffix_name.is_empty()
+                    && let Some(affix_info) = self.tag_names.affix(&inventory_item.suffix_name)
+                {
+                    suffix_rarity = Rarity::from_classification(&affix_info.rarity);
+                    if let Some(name) = &affix_info.name {
+                        suffix = Some(name.clone());
+                    } else if let Some(tag_name) = &affix_info.tag_name
+                        && let Some((name, _)) = self.localize(tag_name)
+                    {
+                        suffix = Some(name);
                     }
                 }
                 let quantity = inventory_item.stack_count;
 
-                let mut item_name = item_name.clone();
-                let item_rarity = {
-                    /* Rare components have this for some reason... Let's give them their own color since we aren't
-                     * detecting them in any other way. */
-                    if item_name.starts_with("^k") {
-                        item_name.drain(0..2);
-                        Rarity::RareComponent
-                    } else {
-                        Rarity::from(item_rarity)
-                    }
-                };
+                let item_rarity = item_rarity(&classification, category, &record_name, color);
 
                 Some(CompleteItem {
                     name: item_name,
@@ -183,8 +484,11 @@ impl ItemLookup {
                     prefix_rarity,
                     suffix,
                     suffix_rarity,
-                    level_req: *level_req,
+                    level_req,
                     quantity,
+                    category,
+                    tier,
+                    inventory_item: inventory_item.clone(),
                 })
             } else {
                 None
@@ -193,17 +497,4 @@ impl ItemLookup {
             None
         }
     }
-
-    pub fn check_item(&self, inventory_item: &InventoryItem, item_source: &str) {
-        if let Some(ci) = self.lookup_item(inventory_item) {
-            let item_name = ci.fmt_searchable_item_name();
-            if item_name.to_lowercase().contains(&self.search_term) {
-                // Most of print logic is handled inside CompleteItem
-                println!("{item_source}: {ci}");
-            }
-        // There are some items with blank fields that might be unused assets. Otherwise log an error.
-        } else if !inventory_item.base_name.is_empty() {
-            println!("No tag found for {}", inventory_item.base_name);
-        }
-    }
 }
@@ -0,0 +1,82 @@
+use crate::arc_parser::ArcArchive;
+use crate::byte_reader::ByteReader;
+use crate::database::Database;
+use crate::error::GdError;
+
+use std::collections::HashMap;
+
+/// Inventory cells are 32x32 pixels in the item bitmaps.
+const CELL_PIXELS: u32 = 32;
+
+/// How many inventory cells items take, as (width, height). The database doesn't say, it has to be worked out from
+/// the size of the item's bitmap.
+pub struct ItemSizes<'a> {
+    database: &'a Database,
+    textures: Vec<ArcArchive>,
+    sizes: HashMap<String, Option<(u32, u32)>>,
+}
+
+impl<'a> ItemSizes<'a> {
+    /// Texture archives are expected in load order, base game first.
+    pub fn new(database: &'a Database, textures: Vec<ArcArchive>) -> Self {
+        Self {
+            database,
+            textures,
+            sizes: HashMap::new(),
+        }
+    }
+
+    /// None if the item has no bitmap, or the bitmap isn't in any of the archives.
+    pub fn size(&mut self, record_name: &str) -> Result<Option<(u32, u32)>, GdError> {
+        if let Some(&size) = self.sizes.get(record_name) {
+            return Ok(size);
+        }
+        let size = self.read_size(record_name)?;
+        self.sizes.insert(record_name.to_string(), size);
+        Ok(size)
+    }
+
+    fn read_size(&self, record_name: &str) -> Result<Option<(u32, u32)>, GdError> {
+        let Some(record) = self.database.record(record_name)? else {
+            return Ok(None);
+        };
+        // Relics and components also have bitmaps for the partly completed ones, these are the finished items
+        let Some(bitmap) = ["bitmap", "relicBitmap"].iter().find_map(|key| record.get_str(key)) else {
+            return Ok(None);
+        };
+        let bitmap = bitmap.replace('\\', "/");
+        // "items/gearweapons/..." is "gearweapons/..." in Items.arc
+        let name_in_archive = bitmap.split_once('/').map_or(bitmap.as_str(), |(_, rest)| rest);
+        for archive in self.textures.iter().rev() {
+            if let Some(data) = archive.extract(name_in_archive)? {
+                let (width, height) = texture_size(data).map_err(|e| e.with_path(archive.path()))?;
+                return Ok(Some((width.div_ceil(CELL_PIXELS).max(1), height.div_ceil(CELL_PIXELS).max(1))));
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// Width and height in pixels of a .tex file, ie. of the DDS image in its first frame.
+pub fn texture_size(data: Vec<u8>) -> Result<(u32, u32), GdError> {
+    let mut reader = ByteReader::from_vec(data);
+    let magic = reader.read_n_bytes(3)?;
+    if magic != b"TEX" {
+        reader.index = 0;
+        return Err(reader.unexpected_value("texture magic", "TEX", String::from_utf8_lossy(&magic)));
+    }
+    let _version = reader.read_byte()?;
+    let _fps = reader.read_u32()?;
+    let _frame_len = reader.read_u32()?;
+    let dds_magic = reader.read_n_bytes(4)?;
+    // The game's own variant of DDS is tagged "DDSR"
+    if dds_magic != b"DDS " && dds_magic != b"DDSR" {
+        reader.index -= 4;
+        return Err(reader.unexpected_value("DDS magic", "DDS", String::from_utf8_lossy(&dds_magic)));
+    }
+    let _header_len = reader.read_u32()?;
+    let _flags = reader.read_u32()?;
+    let height = reader.read_u32()?;
+    let width = reader.read_u32()?;
+    Ok((width, height))
+}
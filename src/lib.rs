@@ -0,0 +1,20 @@
+pub mod arc_parser;
+pub mod arz_parser;
+pub mod backup;
+pub mod byte_reader;
+pub mod cache;
+pub mod config;
+pub mod database;
+pub mod decrypt;
+pub mod encrypt;
+pub mod error;
+pub mod fuzzy;
+pub mod inventory_item;
+pub mod item_category;
+pub mod item_location;
+pub mod item_search;
+pub mod item_size;
+pub mod player;
+pub mod query;
+pub mod stash;
+pub mod worker_pool;
@@ -1,221 +1,1481 @@
-mod arc_parser;
-mod arz_parser;
-mod byte_reader;
-mod config;
-mod decrypt;
-mod inventory_item;
-mod item_search;
-mod player;
-mod stash;
+mod cli;
+mod output;
 
-use byte_reader::ByteReader;
-use config::Config;
-use item_search::ItemLookup;
-use item_search::TagNames;
-use player::CharacterItems;
-use stash::Stash;
+use cli::Command;
+use cli::ContainerKind;
+use cli::Destination;
+use cli::Filters;
+use cli::Grouping;
+use cli::OutputFormat;
+use cli::SearchOptions;
+use cli::SortKey;
+use cli::SortOrder;
+use gdlc::arc_parser;
+use gdlc::arc_parser::ArcArchive;
+use gdlc::arz_parser::ArzArchive;
+use gdlc::backup::Backup;
+use gdlc::backup::Backups;
+use gdlc::backup::FileState;
+use gdlc::cache;
+use gdlc::cache::CacheKey;
+use gdlc::config::Config;
+use gdlc::database::Database;
+use gdlc::error::GdError;
+use gdlc::fuzzy;
+use gdlc::inventory_item::InventoryItem;
+use gdlc::item_location::ItemLocation;
+use gdlc::item_search::CompleteItem;
+use gdlc::item_search::ItemLookup;
+use gdlc::item_search::LocalizationStrings;
+use gdlc::item_search::TagEntries;
+use gdlc::item_search::TagNames;
+use gdlc::item_size::ItemSizes;
+use gdlc::player;
+use gdlc::player::CharacterItems;
+use gdlc::query::Query;
+use gdlc::stash::Stash;
+use gdlc::stash::StashItem;
+use gdlc::stash::StashTab;
+use gdlc::worker_pool::WorkerPool;
 
-use std::io::Error;
-use std::sync::Arc;
-use std::sync::mpsc;
-use std::thread;
+use output::JsonObject;
 
-fn main() -> Result<(), Error> {
-    let mut args = std::env::args();
-    let mut search_term = args.nth(1).unwrap_or_default().to_lowercase();
-    for arg in args {
-        search_term.push_str(&(" ".to_owned() + &arg.to_lowercase()));
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::fs;
+use std::io::IsTerminal;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::time::SystemTime;
+
+fn main() -> ExitCode {
+    let args = match cli::parse_args(std::env::args().skip(1)) {
+        Ok(args) => args,
+        // Scripts need to be able to tell a mistyped command from one that ran
+        Err(usage) => {
+            eprintln!("{usage}");
+            return ExitCode::from(2);
+        }
+    };
+
+    // Escape codes only get in the way of pipes and files. The colored crate already honors NO_COLOR itself.
+    if !std::io::stdout().is_terminal() || std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty()) {
+        colored::control::set_override(false);
     }
 
-    let config = Arc::new(Config::new());
+    let config = Config::new();
+    let pool = args.jobs.map_or_else(WorkerPool::new, WorkerPool::with_threads);
 
-    if config.installation_dir().is_none() {
-        println!("The game installation dir needs to be configured.");
-        return Ok(());
+    let result = match args.command {
+        Command::Search(query, options) => search(&config, &query, &options, args.format, args.no_cache, &pool),
+        Command::Characters => list_characters(&config, args.format, &pool),
+        Command::Move(query, destination, pick) => move_item(&config, &query, &destination, pick, args.no_cache, &pool),
+        Command::Gear(name) => show_gear(&config, &name, args.no_cache, &pool),
+        Command::BackupList => list_backups(&config),
+        Command::BackupRestore(id, file, force) => restore_backup(&config, &id, file.as_deref(), force),
+        Command::DbDump(output_dir) => dump_database(&config, &output_dir),
+        Command::ArcList(archive) => list_arc(&config, &archive),
+        Command::ArcExtract(archive, file) => extract_arc(&config, &archive, file.as_deref()),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            warn(args.format, e);
+            ExitCode::FAILURE
+        }
     }
+}
 
-    if config.save_dir().is_none() {
-        println!("The save dir needs to be configured.");
-        return Ok(());
+/// Problems go along with the results in a table, but to stderr when the results are for another program.
+fn warn(format: OutputFormat, message: impl Display) {
+    if format == OutputFormat::Table {
+        println!("{message}");
+    } else {
+        eprintln!("{message}");
     }
+}
 
-    if let Some(install_dir) = config.installation_dir() {
-        if !install_dir.exists() {
-            println!("The configured installation directory does not exist: {:?}", install_dir);
-            return Ok(());
+fn installation_dir_is_valid(config: &Config, format: OutputFormat) -> bool {
+    match config.installation_dir() {
+        None => {
+            warn(format, "The game installation dir needs to be configured.");
+            false
+        }
+        Some(install_dir) if !install_dir.exists() => {
+            warn(format, format_args!("The configured installation directory does not exist: {:?}", install_dir));
+            false
         }
+        Some(_) => true,
     }
+}
 
-    if let Some(save_dir) = config.save_dir() {
-        if !save_dir.exists() {
-            println!("The configured save directory does not exist: {:?}", save_dir);
-            return Ok(());
+fn save_dir_is_valid(config: &Config, format: OutputFormat) -> bool {
+    match config.save_dir() {
+        None => {
+            warn(format, "The save dir needs to be configured.");
+            false
+        }
+        Some(save_dir) if !save_dir.exists() => {
+            warn(format, format_args!("The configured save directory does not exist: {:?}", save_dir));
+            false
         }
+        Some(_) => true,
     }
+}
 
-    // Read game database files in new threads and send them to "db_done_rx"
-    let (db_done_tx, db_done_rx) = mpsc::channel();
-    {
-        let config = config.clone();
-        thread::spawn(move || {
-            let mut receivers = Vec::new();
-            for path in config.get_databases() {
-                let (db_thread_tx, db_thread_rx) = mpsc::channel();
-                receivers.push(db_thread_rx);
-                thread::spawn(move || {
-                    let (items, affixes) = arz_parser::read_archive(&path).unwrap();
-                    db_thread_tx.send((items, affixes)).unwrap();
-                });
+fn list_characters(config: &Config, format: OutputFormat, pool: &WorkerPool) -> Result<(), GdError> {
+    if !save_dir_is_valid(config, format) {
+        return Ok(());
+    }
+    let save_files = config.get_save_files();
+    let mut characters = Vec::new();
+    for (path, character) in save_files.iter().zip(pool.map(&save_files, CharacterItems::read)) {
+        match character {
+            Ok(character) => characters.push((path, character)),
+            Err(e) => warn(format, format_args!("Unable to read save file: {e}")),
+        }
+    }
+    characters.sort_by(|(_, a), (_, b)| a.header.name.cmp(&b.header.name));
+
+    // Without the game files the class is shown as its tag
+    let mut class_names = HashMap::new();
+    for names in pool.map(&config.get_localization_files(), arc_parser::read_class_names) {
+        match names {
+            Ok(names) => class_names.extend(names),
+            Err(e) => warn(format, format_args!("Unable to read localization file: {e}")),
+        }
+    }
+    let class_name = |tag: &str| match class_names.get(tag) {
+        Some(name) => name.clone(),
+        None if tag.is_empty() => "-".to_string(),
+        None => tag.to_string(),
+    };
+    let mode = |hardcore: bool| if hardcore { "Hardcore" } else { "Softcore" };
+
+    match format {
+        OutputFormat::Table => {
+            println!(
+                "{:<20}  {:<16}  {:>5}  {:<8}  {:<10}  {:>10}  {:>11}  {:<13}  {:<13}  {:>8}  {:<8}  Save file",
+                "Name",
+                "Class",
+                "Level",
+                "Mode",
+                "Difficulty",
+                "Money",
+                "Experience",
+                "Phy/Cun/Spi",
+                "Health/Energy",
+                "Devotion",
+                "Unspent"
+            );
+            for (path, character) in &characters {
+                let (header, info, bio) = (&character.header, &character.info, &character.bio);
+                println!(
+                    "{:<20}  {:<16}  {:>5}  {:<8}  {:<10}  {:>10}  {:>11}  {:<13}  {:<13}  {:>8}  {:<8}  {}",
+                    header.name,
+                    class_name(&header.class_tag),
+                    bio.level,
+                    mode(header.hardcore),
+                    player::difficulty_name(info.greatest_difficulty),
+                    info.money,
+                    bio.experience,
+                    format!("{:.0}/{:.0}/{:.0}", bio.physique, bio.cunning, bio.spirit),
+                    format!("{:.0}/{:.0}", bio.health, bio.energy),
+                    bio.total_devotion,
+                    format!("{}/{}/{}", bio.attribute_points, bio.skill_points, bio.devotion_points),
+                    path.display()
+                );
             }
-            let mut tag_names = TagNames::default();
-            for rcv in receivers {
-                if let Ok((items, affixes)) = rcv.recv() {
-                    tag_names.items.extend(items);
-                    tag_names.affixes.extend(affixes);
-                }
+        }
+        OutputFormat::Json | OutputFormat::Ndjson => {
+            let objects: Vec<JsonObject> = characters
+                .iter()
+                .map(|(path, character)| {
+                    let (header, info, bio) = (&character.header, &character.info, &character.bio);
+                    let mut object = JsonObject::new();
+                    object
+                        .string("name", &header.name)
+                        .string("class", &class_name(&header.class_tag))
+                        .string("class_tag", &header.class_tag)
+                        .number("level", bio.level)
+                        .bool("hardcore", header.hardcore)
+                        .string("greatest_difficulty", player::difficulty_name(info.greatest_difficulty))
+                        .number("money", info.money)
+                        .number("experience", bio.experience)
+                        .number("physique", bio.physique)
+                        .number("cunning", bio.cunning)
+                        .number("spirit", bio.spirit)
+                        .number("health", bio.health)
+                        .number("energy", bio.energy)
+                        .number("total_devotion", bio.total_devotion)
+                        .number("attribute_points", bio.attribute_points)
+                        .number("skill_points", bio.skill_points)
+                        .number("devotion_points", bio.devotion_points)
+                        .string("save_file", &path.to_string_lossy());
+                    object
+                })
+                .collect();
+            print_json(&objects, format);
+        }
+        OutputFormat::Csv => {
+            println!(
+                "{}",
+                output::csv_row(&[
+                    "name",
+                    "class",
+                    "class_tag",
+                    "level",
+                    "hardcore",
+                    "greatest_difficulty",
+                    "money",
+                    "experience",
+                    "physique",
+                    "cunning",
+                    "spirit",
+                    "health",
+                    "energy",
+                    "total_devotion",
+                    "attribute_points",
+                    "skill_points",
+                    "devotion_points",
+                    "save_file",
+                ])
+            );
+            for (path, character) in &characters {
+                let (header, info, bio) = (&character.header, &character.info, &character.bio);
+                println!(
+                    "{}",
+                    output::csv_row(&[
+                        header.name.clone(),
+                        class_name(&header.class_tag),
+                        header.class_tag.clone(),
+                        bio.level.to_string(),
+                        header.hardcore.to_string(),
+                        player::difficulty_name(info.greatest_difficulty).to_string(),
+                        info.money.to_string(),
+                        bio.experience.to_string(),
+                        bio.physique.to_string(),
+                        bio.cunning.to_string(),
+                        bio.spirit.to_string(),
+                        bio.health.to_string(),
+                        bio.energy.to_string(),
+                        bio.total_devotion.to_string(),
+                        bio.attribute_points.to_string(),
+                        bio.skill_points.to_string(),
+                        bio.devotion_points.to_string(),
+                        path.to_string_lossy().to_string(),
+                    ])
+                );
             }
-            db_done_tx.send(tag_names).unwrap();
-        });
+        }
     }
+    Ok(())
+}
 
-    // Read localization strings in new threads and send them to "localization_done_rx"
-    let (localization_done_tx, localization_done_rx) = mpsc::channel();
+fn dump_database(config: &Config, output_dir: &Path) -> Result<(), GdError> {
+    if !installation_dir_is_valid(config, OutputFormat::Table) {
+        return Ok(());
+    }
+    let database = Database::from_config(config)?;
+    for archive in database.archives() {
+        let (count, skipped) = archive.dump(output_dir)?;
+        for message in skipped {
+            eprintln!("{message}");
+        }
+        println!("Extracted {count} records from {:?}", archive.path());
+    }
+    Ok(())
+}
+
+/// Archives can be given relative to the installation directory, eg. "resources/Text_EN.arc".
+fn open_arc(config: &Config, archive: &Path) -> Result<Option<ArcArchive>, GdError> {
+    let mut path = archive.to_path_buf();
+    if !path.exists()
+        && let Some(install_dir) = config.installation_dir()
     {
-        let config = config.clone();
-        thread::spawn(move || {
-            let mut receivers = Vec::new();
-            for path in config.get_localization_files() {
-                let (loc_tx, loc_rx) = mpsc::channel();
-                receivers.push(loc_rx);
-                thread::spawn(move || {
-                    let localization_data = arc_parser::read_archive(&path).unwrap();
-                    loc_tx.send(localization_data).unwrap();
-                });
-            }
-            let mut localization_data = item_search::LocalizationStrings::default();
-            for rcv in receivers {
-                if let Ok(map) = rcv.recv() {
-                    localization_data.extend(map);
+        path = install_dir.join(archive);
+    }
+    if !path.exists() {
+        println!("Archive not found: {:?}", archive);
+        return Ok(None);
+    }
+    Ok(Some(ArcArchive::open(&path)?))
+}
+
+fn list_arc(config: &Config, archive: &Path) -> Result<(), GdError> {
+    let Some(archive) = open_arc(config, archive)? else {
+        return Ok(());
+    };
+    println!("{:<19}  {:>10}  {:>10}  {:>5}  Name", "Modified", "Size", "Compressed", "Parts");
+    for entry in archive.entries() {
+        println!(
+            "{}  {:>10}  {:>10}  {:>5}  {}",
+            entry.modified(),
+            entry.len_decompressed,
+            entry.len_compressed,
+            entry.parts_count,
+            entry.name
+        );
+    }
+    Ok(())
+}
+
+fn extract_arc(config: &Config, archive: &Path, file: Option<&str>) -> Result<(), GdError> {
+    let Some(archive) = open_arc(config, archive)? else {
+        return Ok(());
+    };
+    let mut extracted = 0;
+    for (i, entry) in archive.entries().iter().enumerate() {
+        if file.is_some_and(|file| !file.replace('\\', "/").eq_ignore_ascii_case(&entry.name.replace('\\', "/"))) {
+            continue;
+        }
+        let relative_path = entry.name.replace('\\', "/");
+        // Names come from the archive, don't let them escape the current directory
+        if relative_path.split('/').any(|part| part.is_empty() || part == "." || part == "..") {
+            println!("Skipping file with invalid name {:?}", entry.name);
+            continue;
+        }
+        let path = PathBuf::from(relative_path);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| GdError::io(parent, e))?;
+        }
+        std::fs::write(&path, archive.extract_entry(i)?).map_err(|e| GdError::io(&path, e))?;
+        println!("{}", entry.name);
+        extracted += 1;
+    }
+    if extracted == 0
+        && let Some(file) = file
+    {
+        println!("No file named {file} in {:?}", archive.path());
+    }
+    Ok(())
+}
+
+/// Everything a search reads from disk, one job per file for the worker pool.
+enum LoadJob {
+    Database(PathBuf),
+    Localization(PathBuf),
+    Stash(PathBuf, &'static str),
+    Character(PathBuf),
+}
+
+enum Loaded {
+    Database(ArzArchive),
+    Localization(LocalizationStrings),
+    Stash(SharedStash),
+    Character(Character),
+}
+
+/// A shared stash file as it was read.
+struct SharedStash {
+    label: &'static str,
+    path: PathBuf,
+    modified: SystemTime,
+    stash: Stash,
+}
+
+/// A character's save file as it was read.
+struct Character {
+    path: PathBuf,
+    modified: SystemTime,
+    items: Box<CharacterItems>,
+}
+
+impl LoadJob {
+    fn run(&self) -> Result<Loaded, GdError> {
+        Ok(match self {
+            Self::Database(path) => Loaded::Database(ArzArchive::open(path)?),
+            Self::Localization(path) => Loaded::Localization(arc_parser::read_archive(path)?),
+            // The time is taken before reading, so that a save written meanwhile counts as changed
+            Self::Stash(path, label) => Loaded::Stash(SharedStash {
+                label,
+                path: path.clone(),
+                modified: modified_time(path)?,
+                stash: Stash::new(path)?,
+            }),
+            Self::Character(path) => Loaded::Character(Character {
+                path: path.clone(),
+                modified: modified_time(path)?,
+                items: Box::new(CharacterItems::read(path)?),
+            }),
+        })
+    }
+
+    fn file_kind(&self) -> &'static str {
+        match self {
+            Self::Database(_) => "database",
+            Self::Localization(_) => "localization",
+            Self::Stash(..) => "stash",
+            Self::Character(_) => "save",
+        }
+    }
+}
+
+fn modified_time(path: &Path) -> Result<SystemTime, GdError> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).map_err(|e| GdError::io(path, e))
+}
+
+/// The cached database and localization data, if there is a cache and none of the game files changed since it was
+/// written.
+fn load_cache(
+    cache_file: Option<&Path>,
+    key: Option<&CacheKey>,
+    format: OutputFormat,
+) -> Option<(TagEntries, LocalizationStrings)> {
+    match cache::load(cache_file?, key?) {
+        Ok(game_data) => game_data,
+        // A broken cache gets rebuilt like an outdated one
+        Err(e) => {
+            warn(format, format_args!("Unable to read cache file: {e}"));
+            None
+        }
+    }
+}
+
+/// Every save file, and the names of the items in them.
+struct Inventories {
+    lookup: ItemLookup,
+    stashes: Vec<SharedStash>,
+    characters: Vec<Character>,
+}
+
+/// Problems reading the files are reported as the output format allows.
+fn load_inventories(
+    config: &Config,
+    filters: &Filters,
+    format: OutputFormat,
+    no_cache: bool,
+    pool: &WorkerPool,
+) -> Inventories {
+    let cache_file = config.cache_file().filter(|_| !no_cache);
+    let mut game_files = config.get_databases();
+    game_files.extend(config.get_localization_files());
+    let cache_key = match CacheKey::new(&game_files) {
+        Ok(key) => Some(key),
+        Err(e) => {
+            warn(format, format_args!("Unable to check game files for changes: {e}"));
+            None
+        }
+    };
+    let cached = load_cache(cache_file.as_deref(), cache_key.as_ref(), format);
+
+    // All files are read at the same time, the game files only if they aren't cached
+    let mut jobs = Vec::new();
+    if cached.is_none() {
+        jobs.extend(config.get_databases().into_iter().map(LoadJob::Database));
+        jobs.extend(config.get_localization_files().into_iter().map(LoadJob::Localization));
+    }
+    let (softcore_stash_path, hardcore_stash_path) = config.get_stash_files();
+    jobs.extend(softcore_stash_path.map(|path| LoadJob::Stash(path, "Softcore")));
+    jobs.extend(hardcore_stash_path.map(|path| LoadJob::Stash(path, "Hardcore")));
+    jobs.extend(config.get_save_files().into_iter().map(LoadJob::Character));
+
+    let mut archives = Vec::new();
+    let mut localization_data = LocalizationStrings::default();
+    let mut stashes = Vec::new();
+    let mut characters = Vec::new();
+    // Game files that couldn't be read would stay missing from the cache until the next game patch
+    let mut game_files_complete = true;
+    for (job, loaded) in jobs.iter().zip(pool.map(&jobs, LoadJob::run)) {
+        match loaded {
+            Ok(Loaded::Database(archive)) => archives.push(archive),
+            Ok(Loaded::Localization(map)) => localization_data.extend(map),
+            Ok(Loaded::Stash(stash)) => stashes.push(stash),
+            Ok(Loaded::Character(character)) => characters.push(character),
+            Err(e) => {
+                warn(format, format_args!("Unable to read {} file: {e}", job.file_kind()));
+                if matches!(job, LoadJob::Database(_) | LoadJob::Localization(_)) {
+                    game_files_complete = false;
                 }
             }
-            localization_done_tx.send(localization_data).unwrap();
-        });
+        }
     }
 
-    // Read save files in new threads and send them to "saves_done_rx"
-    let (saves_done_tx, saves_done_rx) = mpsc::channel();
-    {
-        let config = config.clone();
-        thread::spawn(move || {
-            let mut receivers = Vec::new();
-            for save in config.get_save_files() {
-                let (ci_tx, ci_rx) = mpsc::channel::<CharacterItems>();
-                receivers.push(ci_rx);
-                thread::spawn(move || match CharacterItems::read(&save) {
-                    Ok(ci) => {
-                        ci_tx.send(ci).unwrap();
-                    }
+    for name in &filters.characters {
+        if !characters.iter().any(|character| character.items.header.name.to_lowercase() == *name) {
+            warn(format, format_args!("There is no character named {name}."));
+        }
+    }
+    // Before looking up any records, the items of those left out don't need names
+    characters.retain(|character| {
+        let header = &character.items.header;
+        filters.includes_character(&header.name, header.hardcore)
+    });
+    stashes.retain(|shared| filters.includes_shared_stash(shared.label == "Hardcore"));
+
+    let cache_was_valid = cached.is_some();
+    let (entries, localization_data) = cached.unwrap_or_else(|| (TagEntries::new(), localization_data));
+    let mut tag_names = TagNames::new(entries);
+
+    // Only the records the items refer to get decoded, and every item record once for a new cache. With an up to
+    // date cache that's usually none of them, and the database doesn't need to be read at all.
+    let record_names = stashes
+        .iter()
+        .flat_map(|shared| shared.stash.items())
+        .chain(characters.iter().flat_map(|character| character.items.items()))
+        .flat_map(|item| [&item.base_name, &item.prefix_name, &item.suffix_name])
+        .map(String::as_str);
+    let unknown_records = tag_names.unknown(record_names);
+    if !unknown_records.is_empty() || !cache_was_valid {
+        if cache_was_valid {
+            let jobs: Vec<_> = config.get_databases().into_iter().map(LoadJob::Database).collect();
+            for (job, loaded) in jobs.iter().zip(pool.map(&jobs, LoadJob::run)) {
+                match loaded {
+                    Ok(Loaded::Database(archive)) => archives.push(archive),
+                    Ok(_) => unreachable!("database jobs only load databases"),
                     Err(e) => {
-                        println!("Unable to read save file {:?}: {e}", save);
+                        warn(format, format_args!("Unable to read {} file: {e}", job.file_kind()));
+                        game_files_complete = false;
                     }
-                });
-            }
-            let mut all_char_items = Vec::new();
-            for rx in receivers {
-                if let Ok(ci) = rx.recv() {
-                    all_char_items.push(ci);
                 }
             }
-            saves_done_tx.send(all_char_items).unwrap();
-        });
+        }
+        tag_names.set_database(Database::from_archives(archives));
+        tag_names.resolve(&unknown_records, pool);
+        if cache_file.is_some() {
+            tag_names.resolve_items(pool);
+        }
+        for error in tag_names.errors() {
+            warn(format, error);
+        }
+    }
+    // Tiers need every item record decoded, which only a cache is worth doing that for. --no-cache leaves them out.
+    if cache_file.is_some() {
+        tag_names.index_tags();
     }
 
-    // This causes the main thread to wait for the jobs
-    let tag_names = db_done_rx.recv().unwrap();
-    let localization_data = localization_done_rx.recv().unwrap();
-    let all_char_items = saves_done_rx.recv().unwrap();
+    if (!cache_was_valid || tag_names.has_new_entries())
+        && game_files_complete
+        && tag_names.is_complete()
+        && let (Some(cache_file), Some(key)) = (&cache_file, &cache_key)
+        && let Err(e) = cache::save(cache_file, key, &tag_names.entries(), &localization_data)
+    {
+        warn(format, format_args!("Unable to write cache file: {e}"));
+    }
 
-    let lookup = Arc::new(ItemLookup {
-        search_term,
+    let lookup = ItemLookup {
         localization_data,
         tag_names,
-    });
+    };
+    Inventories {
+        lookup,
+        stashes,
+        characters,
+    }
+}
+
+fn search(
+    config: &Config,
+    query: &Query,
+    options: &SearchOptions,
+    format: OutputFormat,
+    no_cache: bool,
+    pool: &WorkerPool,
+) -> Result<(), GdError> {
+    if !installation_dir_is_valid(config, format) || !save_dir_is_valid(config, format) {
+        return Ok(());
+    }
+    let inventories = load_inventories(config, &options.filters, format, no_cache, pool);
+    let mut unknown_records = Vec::new();
+    let mut found = find_items(&inventories, query, &options.filters, &mut unknown_records);
+    // There are some items with blank fields that might be unused assets. Otherwise log an error, away from the
+    // results if they're for another program.
+    for record_name in unknown_records {
+        warn(format, format_args!("No tag found for {record_name}"));
+    }
+    sort_items(&mut found, options.sort);
+    if options.fuzzy {
+        // Stable, so items with as many typos stay in the order asked for
+        found.sort_by_key(|(location, item)| query.score(item, location));
+    }
+    if found.is_empty() {
+        let suggestions = suggest_names(&inventories.lookup, query, pool);
+        if format == OutputFormat::Table {
+            print_suggestions(&suggestions, "No item matches the search.");
+        } else if !suggestions.is_empty() {
+            eprintln!("Did you mean {}?", suggestions.join(", "));
+        }
+    }
+    if let Some(grouping) = options.group {
+        print_groups(&group_items(found, grouping), format);
+        return Ok(());
+    }
+    match format {
+        OutputFormat::Table => {
+            for (location, complete_item) in found {
+                // Most of print logic is handled inside CompleteItem
+                println!("{location}: {complete_item} ({})", item_kind(&complete_item));
+            }
+        }
+        OutputFormat::Json | OutputFormat::Ndjson => {
+            let objects: Vec<JsonObject> = found.iter().map(|(location, item)| item_json(location, item)).collect();
+            print_json(&objects, format);
+        }
+        OutputFormat::Csv => {
+            println!("{}", output::csv_row(&ITEM_CSV_HEADER));
+            for (location, complete_item) in &found {
+                println!("{}", output::csv_row(&item_csv_row(location, complete_item)));
+            }
+        }
+    }
+    Ok(())
+}
 
-    // receiver.recv() all of these to make sure the threads finish
-    let mut search_receivers = Vec::new();
+/// The item and affix names closest to the words searched for, whether anyone has them or not.
+fn suggest_names(lookup: &ItemLookup, query: &Query, pool: &WorkerPool) -> Vec<String> {
+    let words = query.words();
+    if words.is_empty() {
+        return Vec::new();
+    }
+    // The cache already has every item, without one they're only decoded now that nothing matched
+    lookup.tag_names.resolve_items(pool);
+    let names = lookup.names();
+    let closest = fuzzy::closest(&words, names.iter().map(String::as_str), 5);
+    closest.into_iter().map(str::to_string).collect()
+}
 
-    let (softcore_stash_path, hardcore_stash_path) = config.get_stash_files();
-    {
-        let (tx, rx) = mpsc::channel();
-        search_receivers.push(rx);
-        let lookup = lookup.clone();
-        thread::spawn(move || {
-            if let Some(path) = softcore_stash_path {
-                let softcore_stash = Stash::new(&path).unwrap();
-                for (i, tab) in softcore_stash.tabs.iter().enumerate() {
-                    for inventory_item in tab {
-                        lookup.check_item(inventory_item, &format!("Softcore stash tab {}", i + 1));
-                    }
-                }
+fn print_suggestions(suggestions: &[String], nothing_found: &str) {
+    if suggestions.is_empty() {
+        println!("{nothing_found}");
+        return;
+    }
+    println!("{nothing_found} Did you mean:");
+    for name in suggestions {
+        println!("  {name}");
+    }
+}
+
+/// The category, and the tier if the item has several, eg. "ring, tier 2 of 3".
+fn item_kind(item: &CompleteItem) -> String {
+    match item.tier() {
+        Some((tier, count)) => format!("{}, tier {tier} of {count}", item.category()),
+        None => item.category().to_string(),
+    }
+}
+
+/// Copies of the same item, for --group.
+struct ItemGroup {
+    /// The first copy
+    item: CompleteItem,
+    copies: usize,
+    /// Of all copies together
+    quantity: u32,
+    locations: Vec<ItemLocation>,
+}
+
+/// Groups are in the order of their first copy.
+fn group_items(found: Vec<(ItemLocation, CompleteItem)>, grouping: Grouping) -> Vec<ItemGroup> {
+    let mut groups: Vec<ItemGroup> = Vec::new();
+    let mut group_index = HashMap::new();
+    for (location, complete_item) in found {
+        let item = complete_item.inventory_item();
+        let key = match grouping {
+            Grouping::Item => (item.base_name.clone(), item.prefix_name.clone(), item.suffix_name.clone()),
+            Grouping::Base => (item.base_name.clone(), String::new(), String::new()),
+        };
+        match group_index.get(&key) {
+            Some(&i) => {
+                let group: &mut ItemGroup = &mut groups[i];
+                group.copies += 1;
+                group.quantity += complete_item.quantity();
+                group.locations.push(location);
             }
-            tx.send(true).unwrap();
-        });
+            None => {
+                group_index.insert(key, groups.len());
+                let item = match grouping {
+                    Grouping::Item => complete_item,
+                    Grouping::Base => complete_item.without_affixes(),
+                };
+                groups.push(ItemGroup {
+                    quantity: item.quantity(),
+                    item,
+                    copies: 1,
+                    locations: vec![location],
+                });
+            }
+        }
     }
+    groups
+}
 
-    {
-        let (tx, rx) = mpsc::channel();
-        search_receivers.push(rx);
-        let lookup = lookup.clone();
-        thread::spawn(move || {
-            if let Some(path) = hardcore_stash_path {
-                let hardcore_stash = Stash::new(&path).unwrap();
-                for (i, tab) in hardcore_stash.tabs.iter().enumerate() {
-                    for inventory_item in tab {
-                        lookup.check_item(inventory_item, &format!("Hardcore stash tab {}", i + 1));
-                    }
+/// Eg. "Softcore stash tab 1 x2; Alina bag 3". Semicolons, as equipped items have commas in their location.
+fn compact_locations(locations: &[ItemLocation]) -> String {
+    let mut counts: Vec<(String, usize)> = Vec::new();
+    for location in locations {
+        let description = location.to_string();
+        match counts.iter_mut().find(|(known, _)| *known == description) {
+            Some((_, count)) => *count += 1,
+            None => counts.push((description, 1)),
+        }
+    }
+    let parts: Vec<String> = counts
+        .into_iter()
+        .map(|(description, count)| match count {
+            1 => description,
+            _ => format!("{description} x{count}"),
+        })
+        .collect();
+    parts.join("; ")
+}
+
+fn print_groups(groups: &[ItemGroup], format: OutputFormat) {
+    match format {
+        OutputFormat::Table => {
+            for group in groups {
+                // The quantity is only worth showing for stacks, otherwise it's the number of copies
+                let stacked = group.quantity as usize != group.copies;
+                let item = group.item.clone().with_quantity(if stacked { group.quantity } else { 1 });
+                let locations = compact_locations(&group.locations);
+                let item = format!("{} ({})", item.to_string().trim(), item_kind(&item));
+                match group.copies {
+                    1 => println!("{item}: {locations}"),
+                    copies => println!("{item}: {copies} copies in {locations}"),
                 }
             }
-            tx.send(true).unwrap();
-        });
+        }
+        OutputFormat::Json | OutputFormat::Ndjson => {
+            let objects: Vec<JsonObject> = groups.iter().map(group_json).collect();
+            print_json(&objects, format);
+        }
+        OutputFormat::Csv => {
+            println!("{}", output::csv_row(&GROUP_CSV_HEADER));
+            for group in groups {
+                println!("{}", output::csv_row(&group_csv_row(group)));
+            }
+        }
     }
+}
 
-    for char_items in all_char_items {
-        let (tx, rx) = mpsc::channel();
-        search_receivers.push(rx);
-        let lookup = lookup.clone();
-        thread::spawn(move || {
-            for (i, bag) in char_items.inventory.bags.iter().enumerate() {
-                for inventory_item in &bag.items {
-                    lookup.check_item(inventory_item, &format!("{} bag {}", char_items.name, i + 1));
+fn group_json(group: &ItemGroup) -> JsonObject {
+    let complete_item = &group.item;
+    let item = complete_item.inventory_item();
+    let record = |name: &str| (!name.is_empty()).then(|| name.to_string());
+    let locations: Vec<JsonObject> = group.locations.iter().map(location_json).collect();
+    let mut object = JsonObject::new();
+    object
+        .string("name", complete_item.name())
+        .string("full_name", &complete_item.full_name())
+        .string("rarity", complete_item.rarity().name())
+        .string("category", complete_item.category().name())
+        .optional_string("slot", complete_item.category().slot())
+        .optional_number("tier", complete_item.tier().map(|(tier, _)| tier as u32))
+        .number("tier_count", complete_item.tier().map_or(1, |(_, count)| count as u32))
+        .optional_string("prefix", complete_item.prefix())
+        .string("prefix_rarity", complete_item.prefix_rarity().name())
+        .optional_string("suffix", complete_item.suffix())
+        .string("suffix_rarity", complete_item.suffix_rarity().name())
+        .optional_number("level_req", complete_item.level_req())
+        .number("copies", group.copies as u32)
+        .number("quantity", group.quantity)
+        .string("base_record", &item.base_name)
+        .optional_string("prefix_record", record(&item.prefix_name).as_deref())
+        .optional_string("suffix_record", record(&item.suffix_name).as_deref())
+        .array("locations", &locations);
+    object
+}
+
+const GROUP_CSV_HEADER: [&str; 18] = [
+    "name",
+    "full_name",
+    "rarity",
+    "category",
+    "slot",
+    "tier",
+    "tier_count",
+    "prefix",
+    "prefix_rarity",
+    "suffix",
+    "suffix_rarity",
+    "level_req",
+    "copies",
+    "quantity",
+    "base_record",
+    "prefix_record",
+    "suffix_record",
+    "locations",
+];
+
+fn group_csv_row(group: &ItemGroup) -> [String; 18] {
+    let complete_item = &group.item;
+    let item = complete_item.inventory_item();
+    [
+        complete_item.name().to_string(),
+        complete_item.full_name(),
+        complete_item.rarity().name().to_string(),
+        complete_item.category().name().to_string(),
+        complete_item.category().slot().unwrap_or_default().to_string(),
+        complete_item.tier().map(|(tier, _)| tier.to_string()).unwrap_or_default(),
+        complete_item.tier().map_or(1, |(_, count)| count).to_string(),
+        complete_item.prefix().unwrap_or_default().to_string(),
+        complete_item.prefix_rarity().name().to_string(),
+        complete_item.suffix().unwrap_or_default().to_string(),
+        complete_item.suffix_rarity().name().to_string(),
+        complete_item.level_req().map(|level| level.to_string()).unwrap_or_default(),
+        group.copies.to_string(),
+        group.quantity.to_string(),
+        item.base_name.clone(),
+        item.prefix_name.clone(),
+        item.suffix_name.clone(),
+        compact_locations(&group.locations),
+    ]
+}
+
+fn print_json(objects: &[JsonObject], format: OutputFormat) {
+    if format == OutputFormat::Ndjson {
+        output::print_json_lines(objects);
+    } else {
+        output::print_json_array(objects);
+    }
+}
+
+/// A search result with everything known about it. Tabs, bags and weapon sets are counted from 1 as in the text
+/// output, positions in cells from 0.
+fn item_json(location: &ItemLocation, complete_item: &CompleteItem) -> JsonObject {
+    let item = complete_item.inventory_item();
+    let record = |name: &str| (!name.is_empty()).then(|| name.to_string());
+    let mut object = JsonObject::new();
+    object
+        .string("name", complete_item.name())
+        .string("full_name", &complete_item.full_name())
+        .string("rarity", complete_item.rarity().name())
+        .string("category", complete_item.category().name())
+        .optional_string("slot", complete_item.category().slot())
+        .optional_number("tier", complete_item.tier().map(|(tier, _)| tier as u32))
+        .number("tier_count", complete_item.tier().map_or(1, |(_, count)| count as u32))
+        .optional_string("prefix", complete_item.prefix())
+        .string("prefix_rarity", complete_item.prefix_rarity().name())
+        .optional_string("suffix", complete_item.suffix())
+        .string("suffix_rarity", complete_item.suffix_rarity().name())
+        .optional_number("level_req", complete_item.level_req())
+        .number("quantity", complete_item.quantity())
+        .object("location", &location_json(location))
+        .string("base_record", &item.base_name)
+        .optional_string("prefix_record", record(&item.prefix_name).as_deref())
+        .optional_string("suffix_record", record(&item.suffix_name).as_deref())
+        .optional_string("modifier_record", record(&item.modifier_name).as_deref())
+        .optional_string("transmute_record", record(&item.transmute_name).as_deref())
+        .optional_string("component_record", record(&item.component_name).as_deref())
+        .optional_string("relic_bonus_record", record(&item.relic_completion_bonus).as_deref())
+        .optional_string("augment_record", record(&item.augment_name).as_deref())
+        .number("seed", item.seed)
+        .number("relic_seed", item.relic_seed)
+        .number("augment_seed", item.augment_seed);
+    object
+}
+
+fn location_json(location: &ItemLocation) -> JsonObject {
+    let mut location_object = JsonObject::new();
+    location_object
+        .string("description", &location.to_string())
+        .string("kind", location.kind())
+        .string("owner", location.owner())
+        .optional_number("number", location.container_index().map(|index| index as u32 + 1))
+        .optional_number("x", location.position().map(|(x, _)| x))
+        .optional_number("y", location.position().map(|(_, y)| y))
+        .optional_string("slot", location.slot_name());
+    if let ItemLocation::WeaponSet { active, .. } = location {
+        location_object.bool("active", *active);
+    }
+    location_object
+}
+
+const ITEM_CSV_HEADER: [&str; 32] = [
+    "name",
+    "full_name",
+    "rarity",
+    "category",
+    "slot",
+    "tier",
+    "tier_count",
+    "prefix",
+    "prefix_rarity",
+    "suffix",
+    "suffix_rarity",
+    "level_req",
+    "quantity",
+    "location",
+    "location_kind",
+    "location_owner",
+    "location_number",
+    "location_x",
+    "location_y",
+    "location_slot",
+    "location_active",
+    "base_record",
+    "prefix_record",
+    "suffix_record",
+    "modifier_record",
+    "transmute_record",
+    "component_record",
+    "relic_bonus_record",
+    "augment_record",
+    "seed",
+    "relic_seed",
+    "augment_seed",
+];
+
+/// The same as item_json, flattened. Missing values are empty.
+fn item_csv_row(location: &ItemLocation, complete_item: &CompleteItem) -> [String; 32] {
+    let item = complete_item.inventory_item();
+    let optional = |value: Option<u32>| value.map(|value| value.to_string()).unwrap_or_default();
+    let active = match location {
+        ItemLocation::WeaponSet { active, .. } => active.to_string(),
+        _ => String::new(),
+    };
+    [
+        complete_item.name().to_string(),
+        complete_item.full_name(),
+        complete_item.rarity().name().to_string(),
+        complete_item.category().name().to_string(),
+        complete_item.category().slot().unwrap_or_default().to_string(),
+        complete_item.tier().map(|(tier, _)| tier.to_string()).unwrap_or_default(),
+        complete_item.tier().map_or(1, |(_, count)| count).to_string(),
+        complete_item.prefix().unwrap_or_default().to_string(),
+        complete_item.prefix_rarity().name().to_string(),
+        complete_item.suffix().unwrap_or_default().to_string(),
+        complete_item.suffix_rarity().name().to_string(),
+        optional(complete_item.level_req()),
+        complete_item.quantity().to_string(),
+        location.to_string(),
+        location.kind().to_string(),
+        location.owner().to_string(),
+        optional(location.container_index().map(|index| index as u32 + 1)),
+        optional(location.position().map(|(x, _)| x)),
+        optional(location.position().map(|(_, y)| y)),
+        location.slot_name().unwrap_or_default().to_string(),
+        active,
+        item.base_name.clone(),
+        item.prefix_name.clone(),
+        item.suffix_name.clone(),
+        item.modifier_name.clone(),
+        item.transmute_name.clone(),
+        item.component_name.clone(),
+        item.relic_completion_bonus.clone(),
+        item.augment_name.clone(),
+        item.seed.to_string(),
+        item.relic_seed.to_string(),
+        item.augment_seed.to_string(),
+    ]
+}
+
+fn sort_items(found: &mut [(ItemLocation, CompleteItem)], sort: SortOrder) {
+    // Stable, so items at the same location keep the order they were found in
+    match sort.key {
+        SortKey::Location => found.sort_by_cached_key(|(location, _)| location.sort_key()),
+        SortKey::Name => {
+            found.sort_by_cached_key(|(location, item)| (item.full_name().to_lowercase(), location.sort_key()))
+        }
+        SortKey::Rarity => found.sort_by_cached_key(|(location, item)| (item.rarity(), location.sort_key())),
+        SortKey::Level => found.sort_by_cached_key(|(location, item)| (item.level_req(), location.sort_key())),
+    }
+    if sort.reverse {
+        found.reverse();
+    }
+}
+
+/// Every item matching the query, in the containers the filters leave in. Items whose records aren't items are left
+/// out and added to unknown_records.
+fn find_items(
+    inventories: &Inventories,
+    query: &Query,
+    filters: &Filters,
+    unknown_records: &mut Vec<String>,
+) -> Vec<(ItemLocation, CompleteItem)> {
+    let lookup = &inventories.lookup;
+    let mut found = Vec::new();
+    let mut check = |inventory_item: &InventoryItem, location: ItemLocation| match lookup.lookup_item(inventory_item) {
+        Some(complete_item) if query.matches(&complete_item, &location) => found.push((location, complete_item)),
+        Some(_) => (),
+        None if inventory_item.base_name.is_empty() => (),
+        None => unknown_records.push(inventory_item.base_name.clone()),
+    };
+
+    for shared in &inventories.stashes {
+        let hardcore = shared.label == "Hardcore";
+        for (tab, stash_tab) in shared.stash.tabs.iter().enumerate() {
+            for stash_item in &stash_tab.items {
+                let location = ItemLocation::SharedStash {
+                    hardcore,
+                    tab,
+                    x: stash_item.x as u32,
+                    y: stash_item.y as u32,
+                };
+                check(&stash_item.item, location);
+            }
+        }
+    }
+
+    for character in &inventories.characters {
+        let char_items = &character.items;
+        let name = &char_items.header.name;
+        if filters.includes(ContainerKind::Inventory) {
+            for (bag, inventory_bag) in char_items.inventory.bags.iter().enumerate() {
+                for bag_item in &inventory_bag.items {
+                    let location = ItemLocation::Bag {
+                        character: name.clone(),
+                        bag,
+                        x: bag_item.x,
+                        y: bag_item.y,
+                    };
+                    check(&bag_item.item, location);
                 }
             }
+        }
 
-            for (i, tab) in char_items.stash.tabs.iter().enumerate() {
-                for inventory_item in tab {
-                    lookup.check_item(inventory_item, &format!("{} stash tab {}", char_items.name, i + 1));
+        if filters.includes(ContainerKind::Stash) {
+            for (tab, stash_tab) in char_items.stash.tabs.iter().enumerate() {
+                for stash_item in &stash_tab.items {
+                    let location = ItemLocation::CharacterStash {
+                        character: name.clone(),
+                        tab,
+                        x: stash_item.x as u32,
+                        y: stash_item.y as u32,
+                    };
+                    check(&stash_item.item, location);
                 }
             }
+        }
+
+        if !filters.includes(ContainerKind::Equipped) {
+            continue;
+        }
+        for (slot, equipment) in char_items.inventory.equipment.iter().enumerate() {
+            let location = ItemLocation::Equipped {
+                character: name.clone(),
+                slot,
+            };
+            check(&equipment.item, location);
+        }
+
+        let weapon_sets = [&char_items.inventory.weapon_set_1, &char_items.inventory.weapon_set_2];
+        let active_set = char_items.inventory.active_weapon_set();
+        for (set, weapon_set) in weapon_sets.into_iter().enumerate() {
+            for (slot, equipment) in weapon_set.iter().enumerate() {
+                let location = ItemLocation::WeaponSet {
+                    character: name.clone(),
+                    set,
+                    slot,
+                    active: set == active_set,
+                };
+                check(&equipment.item, location);
+            }
+        }
+    }
+    found
+}
+
+fn show_gear(config: &Config, name: &str, no_cache: bool, pool: &WorkerPool) -> Result<(), GdError> {
+    if !installation_dir_is_valid(config, OutputFormat::Table) || !save_dir_is_valid(config, OutputFormat::Table) {
+        return Ok(());
+    }
+    let filters = Filters {
+        characters: vec![name.to_lowercase()],
+        ..Filters::default()
+    };
+    // Says so itself if there is no such character
+    let inventories = load_inventories(config, &filters, OutputFormat::Table, no_cache, pool);
+    let describe = |item: &InventoryItem| {
+        if item.base_name.is_empty() {
+            return "(empty)".to_string();
+        }
+        match inventories.lookup.lookup_item(item) {
+            Some(complete_item) => complete_item.to_string().trim().to_string(),
+            None => item.base_name.clone(),
+        }
+    };
 
-            for inventory_item in char_items.inventory.equipment.iter() {
-                lookup.check_item(&inventory_item.item, &format!("Equipped by {}", char_items.name));
+    // A softcore and a hardcore character can have the same name
+    for (i, character) in inventories.characters.iter().enumerate() {
+        let (header, inventory) = (&character.items.header, &character.items.inventory);
+        if i > 0 {
+            println!();
+        }
+        let mode = if header.hardcore { "Hardcore" } else { "Softcore" };
+        println!("{}, level {} {mode}", header.name, character.items.bio.level);
+        for (slot_name, equipment) in player::EQUIPMENT_SLOT_NAMES.iter().zip(&inventory.equipment) {
+            println!("  {:<11} {}", format!("{slot_name}:"), describe(&equipment.item));
+        }
+        let active_set = inventory.active_weapon_set();
+        for (set, weapon_set) in [&inventory.weapon_set_1, &inventory.weapon_set_2].into_iter().enumerate() {
+            let active = if set == active_set { " (active)" } else { "" };
+            println!("  Weapon set {}{active}", set + 1);
+            for (slot_name, equipment) in player::WEAPON_SLOT_NAMES.iter().zip(weapon_set) {
+                println!("    {:<10} {}", format!("{slot_name}:"), describe(&equipment.item));
             }
+        }
+    }
+    Ok(())
+}
+
+/// A save file among the loaded inventories.
+#[derive(Clone, Copy, PartialEq)]
+enum SaveRef {
+    Stash(usize),
+    Character(usize),
+}
 
-            for inventory_item in char_items.inventory.weapon_set_1.iter() {
-                lookup.check_item(&inventory_item.item, &format!("Equipped by {}, weapon set 1", char_items.name));
+/// Somewhere an item can be moved out of. Equipped items aren't, the character would have to take them off first.
+#[derive(Clone, Copy, PartialEq)]
+enum Container {
+    StashTab(SaveRef, usize),
+    Bag(usize, usize), // character, bag
+}
+
+impl Container {
+    fn save(&self) -> SaveRef {
+        match *self {
+            Self::StashTab(save, _) => save,
+            Self::Bag(character, _) => SaveRef::Character(character),
+        }
+    }
+}
+
+impl Inventories {
+    fn path(&self, save: SaveRef) -> &Path {
+        match save {
+            SaveRef::Stash(i) => &self.stashes[i].path,
+            SaveRef::Character(i) => &self.characters[i].path,
+        }
+    }
+
+    fn modified(&self, save: SaveRef) -> SystemTime {
+        match save {
+            SaveRef::Stash(i) => self.stashes[i].modified,
+            SaveRef::Character(i) => self.characters[i].modified,
+        }
+    }
+
+    fn is_hardcore(&self, save: SaveRef) -> bool {
+        match save {
+            SaveRef::Stash(i) => self.stashes[i].label == "Hardcore",
+            SaveRef::Character(i) => self.characters[i].items.header.hardcore,
+        }
+    }
+
+    fn stash_tabs(&self, save: SaveRef) -> &[StashTab] {
+        match save {
+            SaveRef::Stash(i) => &self.stashes[i].stash.tabs,
+            SaveRef::Character(i) => &self.characters[i].items.stash.tabs,
+        }
+    }
+
+    fn stash_tabs_mut(&mut self, save: SaveRef) -> &mut Vec<StashTab> {
+        match save {
+            SaveRef::Stash(i) => &mut self.stashes[i].stash.tabs,
+            SaveRef::Character(i) => &mut self.characters[i].items.stash.tabs,
+        }
+    }
+
+    /// Named as in search results, eg. "Softcore stash tab 3" or "Alina bag 2".
+    fn container_name(&self, container: Container) -> String {
+        match container {
+            Container::StashTab(SaveRef::Stash(i), tab) => format!("{} stash tab {}", self.stashes[i].label, tab + 1),
+            Container::StashTab(SaveRef::Character(i), tab) => {
+                format!("{} stash tab {}", self.characters[i].items.header.name, tab + 1)
             }
+            Container::Bag(i, bag) => format!("{} bag {}", self.characters[i].items.header.name, bag + 1),
+        }
+    }
 
-            for inventory_item in char_items.inventory.weapon_set_2.iter() {
-                lookup.check_item(&inventory_item.item, &format!("Equipped by {}, weapon set 2", char_items.name));
+    /// Where the item is, as search results have it.
+    fn location(&self, container: Container, index: usize) -> ItemLocation {
+        match container {
+            Container::StashTab(save, tab) => {
+                let stash_item = &self.stash_tabs(save)[tab].items[index];
+                let (x, y) = (stash_item.x as u32, stash_item.y as u32);
+                match save {
+                    SaveRef::Stash(i) => ItemLocation::SharedStash {
+                        hardcore: self.stashes[i].label == "Hardcore",
+                        tab,
+                        x,
+                        y,
+                    },
+                    SaveRef::Character(i) => ItemLocation::CharacterStash {
+                        character: self.characters[i].items.header.name.clone(),
+                        tab,
+                        x,
+                        y,
+                    },
+                }
             }
+            Container::Bag(i, bag) => {
+                let bag_item = &self.characters[i].items.inventory.bags[bag].items[index];
+                ItemLocation::Bag {
+                    character: self.characters[i].items.header.name.clone(),
+                    bag,
+                    x: bag_item.x,
+                    y: bag_item.y,
+                }
+            }
+        }
+    }
 
-            tx.send(true).unwrap();
+    fn item(&self, container: Container, index: usize) -> &InventoryItem {
+        match container {
+            Container::StashTab(save, tab) => &self.stash_tabs(save)[tab].items[index].item,
+            Container::Bag(i, bag) => &self.characters[i].items.inventory.bags[bag].items[index].item,
+        }
+    }
+
+    fn take_item(&mut self, container: Container, index: usize) -> InventoryItem {
+        match container {
+            Container::StashTab(save, tab) => self.stash_tabs_mut(save)[tab].items.remove(index).item,
+            Container::Bag(i, bag) => self.characters[i].items.inventory.bags[bag].items.remove(index).item,
+        }
+    }
+
+    /// Write a save file through the backup, which needs to have a copy of it.
+    fn write(&self, save: SaveRef, backup: &mut Backup) -> Result<(), GdError> {
+        let bytes = match save {
+            SaveRef::Stash(i) => self.stashes[i].stash.to_bytes(),
+            SaveRef::Character(i) => self.characters[i].items.to_bytes(),
+        };
+        backup.write(self.path(save), &bytes)
+    }
+}
+
+fn move_item(
+    config: &Config,
+    query: &Query,
+    destination: &Destination,
+    pick: Option<usize>,
+    no_cache: bool,
+    pool: &WorkerPool,
+) -> Result<(), GdError> {
+    if !installation_dir_is_valid(config, OutputFormat::Table) || !save_dir_is_valid(config, OutputFormat::Table) {
+        return Ok(());
+    }
+    let mut inventories = load_inventories(config, &Filters::default(), OutputFormat::Table, no_cache, pool);
+
+    let target = inventories
+        .stashes
+        .iter()
+        .position(|shared| shared.label.eq_ignore_ascii_case(&destination.owner))
+        .map(SaveRef::Stash)
+        .or_else(|| {
+            let owner = destination.owner.to_lowercase();
+            let position = inventories.characters.iter().position(|c| c.items.header.name.to_lowercase() == owner);
+            position.map(SaveRef::Character)
         });
+    let Some(target) = target else {
+        println!("There is no {} stash.", destination.owner);
+        return Ok(());
+    };
+    let tab_count = inventories.stash_tabs(target).len();
+    if destination.tab > tab_count {
+        println!("The {} stash only has {tab_count} tabs.", destination.owner);
+        return Ok(());
     }
+    let tab = destination.tab - 1;
 
-    // This makes sure all threads finish
-    for rx in search_receivers {
-        let _ = rx.recv().unwrap();
+    let mut containers = Vec::new();
+    for (i, shared) in inventories.stashes.iter().enumerate() {
+        containers.extend((0..shared.stash.tabs.len()).map(|index| Container::StashTab(SaveRef::Stash(i), index)));
+    }
+    for (i, character) in inventories.characters.iter().enumerate() {
+        containers.extend((0..character.items.inventory.bags.len()).map(|bag| Container::Bag(i, bag)));
+        let tabs = 0..character.items.stash.tabs.len();
+        containers.extend(tabs.map(|tab| Container::StashTab(SaveRef::Character(i), tab)));
+    }
+    let mut candidates = Vec::new();
+    for container in containers {
+        let items: Vec<&InventoryItem> = match container {
+            Container::StashTab(save, tab) => inventories.stash_tabs(save)[tab].items().collect(),
+            Container::Bag(i, bag) => inventories.characters[i].items.inventory.bags[bag].items().collect(),
+        };
+        for (index, item) in items.into_iter().enumerate() {
+            if let Some(complete_item) = inventories.lookup.lookup_item(item)
+                && query.matches(&complete_item, &inventories.location(container, index))
+            {
+                let name = complete_item.to_string().trim().to_string();
+                candidates.push((container, index, name, complete_item.full_name()));
+            }
+        }
     }
 
+    let (source, index, name, plain_name) = match (candidates.len(), pick) {
+        (0, _) => {
+            let suggestions = suggest_names(&inventories.lookup, query, pool);
+            print_suggestions(&suggestions, "No item in a stash or bag matches the search.");
+            return Ok(());
+        }
+        (1, None) => candidates.swap_remove(0),
+        (count, Some(pick)) if pick <= count => candidates.swap_remove(pick - 1),
+        _ => {
+            println!("{} items match, pick one with --pick N:", candidates.len());
+            for (i, (container, _, name, _)) in candidates.iter().enumerate() {
+                println!("{:>4}  {}: {name}", i + 1, inventories.container_name(*container));
+            }
+            return Ok(());
+        }
+    };
+
+    let destination_name = inventories.container_name(Container::StashTab(target, tab));
+    if source == Container::StashTab(target, tab) {
+        println!("{name} is already in {destination_name}.");
+        return Ok(());
+    }
+    if inventories.is_hardcore(source.save()) != inventories.is_hardcore(target) {
+        println!("Items can't be moved between softcore and hardcore.");
+        return Ok(());
+    }
+
+    let texture_paths = config.get_item_texture_archives();
+    if texture_paths.is_empty() {
+        println!("Item sizes are read from resources/Items.arc, which is missing from the installation directory.");
+        return Ok(());
+    }
+    let mut textures = Vec::new();
+    for path in &texture_paths {
+        textures.push(ArcArchive::open(path)?);
+    }
+    // Already read unless every name was cached
+    let opened_database;
+    let database = match inventories.lookup.tag_names.database() {
+        Some(database) => database,
+        None => {
+            opened_database = Database::from_config(config)?;
+            &opened_database
+        }
+    };
+    let mut item_sizes = ItemSizes::new(database, textures);
+    // An item of unknown size could end up overlapping others, which the game might not take well
+    let Some(size) = item_sizes.size(&inventories.item(source, index).base_name)? else {
+        println!("Unable to tell how big {name} is, so it wasn't moved.");
+        return Ok(());
+    };
+    let mut occupied = Vec::new();
+    for stash_item in &inventories.stash_tabs(target)[tab].items {
+        match item_sizes.size(&stash_item.item.base_name)? {
+            Some(size) => occupied.push(size),
+            None => {
+                println!(
+                    "Unable to tell how big {} in {destination_name} is, so there's no telling where there is room.",
+                    stash_item.item.base_name
+                );
+                return Ok(());
+            }
+        }
+    }
+    let Some((x, y)) = inventories.stash_tabs(target)[tab].free_position(size, &occupied) else {
+        println!("There is no room for {name} in {destination_name}.");
+        return Ok(());
+    };
+
+    // Most likely the game saved the character meanwhile, writing our copy would undo that
+    for save in [target, source.save()] {
+        let path = inventories.path(save);
+        if modified_time(path)? != inventories.modified(save) {
+            println!("{:?} changed since it was read, nothing was moved. Close the game and try again.", path);
+            return Ok(());
+        }
+    }
+
+    let Some(backups) = open_backups(config) else {
+        return Ok(());
+    };
+    let source_name = inventories.container_name(source);
+    let reason = format!("move {plain_name} from {source_name} to {destination_name}");
+    let mut backup = backups.create(&reason, &[inventories.path(target), inventories.path(source.save())])?;
+
+    let item = inventories.take_item(source, index);
+    inventories.stash_tabs_mut(target)[tab].items.push(StashItem {
+        item,
+        x: x as f32,
+        y: y as f32,
+    });
+    // The destination is written first, if writing the source fails the item is duplicated rather than lost
+    inventories.write(target, &mut backup)?;
+    if source.save() != target {
+        inventories.write(source.save(), &mut backup)?;
+    }
+    println!("Moved {name} from {source_name} to {destination_name}, column {} row {}.", x + 1, y + 1);
+    println!("Undo with: gdlc backup restore {}", backup.id);
+    Ok(())
+}
+
+fn open_backups(config: &Config) -> Option<Backups> {
+    let (Some(backup_dir), Some(save_dir)) = (config.backup_dir(), config.save_dir()) else {
+        println!("There is nowhere to back up the save files to, set backup_dir in the config.");
+        return None;
+    };
+    Some(Backups::new(backup_dir, save_dir, config.backup_count()))
+}
+
+fn list_backups(config: &Config) -> Result<(), GdError> {
+    if !save_dir_is_valid(config, OutputFormat::Table) {
+        return Ok(());
+    }
+    let Some(backups) = open_backups(config) else {
+        return Ok(());
+    };
+    let list = backups.list()?;
+    if list.is_empty() {
+        println!("No backups yet. They are taken whenever gdlc changes a save file.");
+    }
+    for backup in list.iter().rev() {
+        println!("{}  {} UTC  {}", backup.id, backup.created(), backup.reason);
+        for file in &backup.files {
+            let note = match backup.state(file)? {
+                FileState::Unchanged => " (same as now)",
+                FileState::Written => "",
+                FileState::ChangedSince => " (changed since, probably by the game)",
+                FileState::Missing => " (missing now)",
+            };
+            println!("    {}{note}", file.path.display());
+        }
+    }
+    Ok(())
+}
+
+fn restore_backup(config: &Config, id: &str, file_name: Option<&str>, force: bool) -> Result<(), GdError> {
+    if !save_dir_is_valid(config, OutputFormat::Table) {
+        return Ok(());
+    }
+    let Some(backups) = open_backups(config) else {
+        return Ok(());
+    };
+    let Some(backup) = backups.get(id)? else {
+        println!("There is no backup {id}, see gdlc backup list.");
+        return Ok(());
+    };
+    // Either the whole path within the save directory or just its end, eg. "_Alina/player.gdc"
+    let files: Vec<_> =
+        backup.files.iter().filter(|file| file_name.is_none_or(|name| file.path.ends_with(name))).collect();
+    if let Some(file_name) = file_name
+        && files.is_empty()
+    {
+        println!("Backup {id} doesn't have {file_name}.");
+        return Ok(());
+    }
+
+    let mut to_restore = Vec::new();
+    let mut changed_since = false;
+    for file in files {
+        match backup.state(file)? {
+            FileState::Unchanged => println!("{} is the same as in the backup.", file.path.display()),
+            FileState::ChangedSince if !force => {
+                println!("{} was changed after gdlc wrote it, probably by the game.", file.path.display());
+                changed_since = true;
+            }
+            _ => to_restore.push(file),
+        }
+    }
+    // Restoring only some files of a move could leave the item in two places, or none
+    if changed_since {
+        println!("Nothing was restored. Use --force to restore anyway, which undoes those changes too.");
+        return Ok(());
+    }
+    if to_restore.is_empty() {
+        return Ok(());
+    }
+
+    // The files being replaced are backed up as well, so that a restore can be undone like anything else
+    let undo = backups.restore(&backup, &to_restore)?;
+    for file in to_restore {
+        println!("Restored {}", file.path.display());
+    }
+    println!("Undo with: gdlc backup restore {}", undo.id);
     Ok(())
 }
@@ -0,0 +1,135 @@
+use std::fmt;
+use std::fmt::Display;
+use std::fmt::Write;
+
+/// A JSON object with its fields in insertion order. Enough for our flat-ish records without pulling in serde.
+#[derive(Default)]
+pub struct JsonObject {
+    fields: Vec<(&'static str, String)>, // values are already encoded
+}
+
+impl JsonObject {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn string(&mut self, key: &'static str, value: &str) -> &mut Self {
+        self.fields.push((key, json_string(value)));
+        self
+    }
+
+    pub fn number(&mut self, key: &'static str, value: impl Into<f64>) -> &mut Self {
+        let value = value.into();
+        // JSON has no NaN or infinity
+        let encoded = if value.is_finite() { value.to_string() } else { "null".to_string() };
+        self.fields.push((key, encoded));
+        self
+    }
+
+    pub fn bool(&mut self, key: &'static str, value: bool) -> &mut Self {
+        self.fields.push((key, value.to_string()));
+        self
+    }
+
+    pub fn null(&mut self, key: &'static str) -> &mut Self {
+        self.fields.push((key, "null".to_string()));
+        self
+    }
+
+    pub fn object(&mut self, key: &'static str, value: &JsonObject) -> &mut Self {
+        self.fields.push((key, value.to_string()));
+        self
+    }
+
+    pub fn array(&mut self, key: &'static str, values: &[JsonObject]) -> &mut Self {
+        let values: Vec<String> = values.iter().map(JsonObject::to_string).collect();
+        self.fields.push((key, format!("[{}]", values.join(", "))));
+        self
+    }
+
+    /// The string, or null if there is none.
+    pub fn optional_string(&mut self, key: &'static str, value: Option<&str>) -> &mut Self {
+        match value {
+            Some(value) => self.string(key, value),
+            None => self.null(key),
+        }
+    }
+
+    /// The number, or null if there is none.
+    pub fn optional_number(&mut self, key: &'static str, value: Option<impl Into<f64>>) -> &mut Self {
+        match value {
+            Some(value) => self.number(key, value),
+            None => self.null(key),
+        }
+    }
+}
+
+impl Display for JsonObject {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{{")?;
+        for (i, (key, value)) in self.fields.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}: {value}", json_string(key))?;
+        }
+        write!(f, "}}")
+    }
+}
+
+/// One object per line, so that the output stays greppable.
+pub fn print_json_array(objects: &[JsonObject]) {
+    if objects.is_empty() {
+        println!("[]");
+        return;
+    }
+    println!("[");
+    for (i, object) in objects.iter().enumerate() {
+        let separator = if i + 1 < objects.len() { "," } else { "" };
+        println!("  {object}{separator}");
+    }
+    println!("]");
+}
+
+/// Newline delimited JSON, one object per line and nothing else.
+pub fn print_json_lines(objects: &[JsonObject]) {
+    for object in objects {
+        println!("{object}");
+    }
+}
+
+pub fn json_string(string: &str) -> String {
+    let mut ret = String::with_capacity(string.len() + 2);
+    ret.push('"');
+    for c in string.chars() {
+        match c {
+            '"' => ret.push_str("\\\""),
+            '\\' => ret.push_str("\\\\"),
+            '\n' => ret.push_str("\\n"),
+            '\r' => ret.push_str("\\r"),
+            '\t' => ret.push_str("\\t"),
+            c if c.is_control() => {
+                let _ = write!(ret, "\\u{:04x}", c as u32);
+            }
+            c => ret.push(c),
+        }
+    }
+    ret.push('"');
+    ret
+}
+
+/// A CSV line as in RFC 4180. Fields are quoted only when they need to be.
+pub fn csv_row<S: AsRef<str>>(fields: &[S]) -> String {
+    let fields: Vec<String> = fields
+        .iter()
+        .map(|field| {
+            let field = field.as_ref();
+            if field.contains([',', '"', '\n', '\r']) {
+                format!("\"{}\"", field.replace('"', "\"\""))
+            } else {
+                field.to_string()
+            }
+        })
+        .collect();
+    fields.join(",")
+}
@@ -1,38 +1,66 @@
+use crate::decrypt::Decrypt;
+use crate::decrypt::RawBlock;
+use crate::encrypt;
+use crate::encrypt::Encrypt;
+use crate::error::GdError;
 use crate::inventory_item::InventoryItem;
-use crate::stash;
-use crate::stash::StashItem;
+use crate::stash::StashTab;
 
-use super::decrypt::Decrypt;
-
-use std::io::Error;
+use std::path::Path;
 use std::path::PathBuf;
 
 const EQUIPMENT_SLOTS: usize = 12;
 
+/// In the order of Inventory::equipment.
+pub const EQUIPMENT_SLOT_NAMES: [&str; EQUIPMENT_SLOTS] = [
+    "Head",
+    "Amulet",
+    "Chest",
+    "Legs",
+    "Feet",
+    "Hands",
+    "Ring 1",
+    "Ring 2",
+    "Belt",
+    "Shoulders",
+    "Medal",
+    "Relic",
+];
+
+/// In the order of the weapon sets.
+pub const WEAPON_SLOT_NAMES: [&str; 2] = ["Main hand", "Off hand"];
+
 pub struct PlayerStash {
-    pub tabs: Vec<Vec<InventoryItem>>
+    pub tabs: Vec<StashTab>,
 }
+
 impl PlayerStash {
-    fn read(decrypt: &mut Decrypt) -> Result<PlayerStash, Error> {
-        let (start, block) = decrypt.read_block_start();
-        assert!(start == 4, "Expected player stash block to start with 0.");
-        assert!(decrypt.read_int() == 6, "Expected character stash version to be 6.");
-        let num_tabs = decrypt.read_int();
-        let mut tabs = Vec::with_capacity(num_tabs as usize);
+    fn read(decrypt: &mut Decrypt) -> Result<PlayerStash, GdError> {
+        let block = decrypt.read_block_start(4)?;
+        decrypt.expect_int("character stash version", 6)?;
+        let num_tabs = decrypt.read_int()?;
+        let mut tabs = Vec::with_capacity(decrypt.capacity_for(num_tabs, 4));
         for _ in 0..num_tabs {
-            tabs.push(stash::read_stash_tab(decrypt)?);
+            tabs.push(StashTab::read(decrypt)?);
         }
-        decrypt.read_block_end(&block).unwrap();
-        Ok(PlayerStash { 
-            tabs
-        })
+        decrypt.read_block_end(&block)?;
+        Ok(PlayerStash { tabs })
+    }
+
+    fn write(&self, encrypt: &mut Encrypt) {
+        let block = encrypt.write_block_start(4);
+        encrypt.write_int(6);
+        encrypt.write_int(self.tabs.len() as u32);
+        for tab in &self.tabs {
+            tab.write(encrypt);
+        }
+        encrypt.write_block_end(block);
     }
 }
 
 #[derive(Debug)]
 #[allow(dead_code)]
 pub struct Inventory {
-    num_bags: u32,
     pub bags: Vec<Bag>,
     pub equipment: [InventoryEquipment; EQUIPMENT_SLOTS],
     pub weapon_set_1: [InventoryEquipment; 2],
@@ -53,65 +81,107 @@ pub struct InventoryEquipment {
 }
 
 impl InventoryEquipment {
-    fn read(decrypt: &mut Decrypt) -> Self {
-        Self {
-            item: InventoryItem::read(decrypt).unwrap(),
-            attached: decrypt.read_byte(),
-        }
+    fn read(decrypt: &mut Decrypt) -> Result<Self, GdError> {
+        Ok(Self {
+            item: InventoryItem::read(decrypt)?,
+            attached: decrypt.read_byte()?,
+        })
+    }
+
+    fn write(&self, encrypt: &mut Encrypt) {
+        self.item.write(encrypt);
+        encrypt.write_byte(self.attached);
+    }
+}
+
+/// An item and its position in a bag, in grid cells from the top left.
+#[derive(Debug)]
+pub struct BagItem {
+    pub item: InventoryItem,
+    pub x: u32,
+    pub y: u32,
+}
+
+impl BagItem {
+    fn read(decrypt: &mut Decrypt) -> Result<Self, GdError> {
+        Ok(Self {
+            item: InventoryItem::read(decrypt)?,
+            x: decrypt.read_int()?,
+            y: decrypt.read_int()?,
+        })
+    }
+
+    fn write(&self, encrypt: &mut Encrypt) {
+        self.item.write(encrypt);
+        encrypt.write_int(self.x);
+        encrypt.write_int(self.y);
     }
 }
 
 #[derive(Debug)]
 pub struct Bag {
-    _some_bool: u8,
-    pub items: Vec<InventoryItem>,
+    some_bool: u8,
+    pub items: Vec<BagItem>,
 }
 
 impl Bag {
-    fn read(decrypt: &mut Decrypt) -> Self {
-        let (start, block) = decrypt.read_block_start();
-        assert!(start == 0, "expected non-zero start of bag block");
+    fn read(decrypt: &mut Decrypt) -> Result<Self, GdError> {
+        let block = decrypt.read_block_start(0)?;
         let ret = Self {
-            _some_bool: decrypt.read_byte(),
+            some_bool: decrypt.read_byte()?,
             items: {
-                let len = decrypt.read_int();
-                let mut ret = Vec::with_capacity(len as usize);
+                let len = decrypt.read_int()?;
+                let mut ret = Vec::with_capacity(decrypt.capacity_for(len, 4));
                 for _ in 0..len {
-                    ret.push(StashItem::read(decrypt).unwrap().item);
+                    ret.push(BagItem::read(decrypt)?);
                 }
                 ret
             },
         };
-        decrypt.read_block_end(&block).unwrap();
-        ret
+        decrypt.read_block_end(&block)?;
+        Ok(ret)
+    }
+
+    fn write(&self, encrypt: &mut Encrypt) {
+        let block = encrypt.write_block_start(0);
+        encrypt.write_byte(self.some_bool);
+        encrypt.write_int(self.items.len() as u32);
+        for item in &self.items {
+            item.write(encrypt);
+        }
+        encrypt.write_block_end(block);
+    }
+
+    pub fn items(&self) -> impl Iterator<Item = &InventoryItem> {
+        self.items.iter().map(|bag_item| &bag_item.item)
     }
 }
 
 impl Inventory {
-    fn read(decrypt: &mut Decrypt) -> Self {
-        let (start, block) = decrypt.read_block_start();
-        assert_eq!(start, 3);
-        assert_eq!(decrypt.read_int(), 4);
-        let flag = decrypt.read_byte();
+    fn read(decrypt: &mut Decrypt) -> Result<Self, GdError> {
+        let block = decrypt.read_block_start(3)?;
+        decrypt.expect_int("inventory version", 4)?;
+        let offset = decrypt.position();
+        let flag = decrypt.read_byte()?;
         if flag == 0 {
-            panic!("This byte was supposed to be 0. The file format will be wrong and I can't continue.");
+            // The file format will be wrong and we can't continue.
+            return Err(decrypt.unexpected_value(offset, "inventory flag", "non-zero", flag));
         }
-        let num_bags = decrypt.read_int();
-        let focused = decrypt.read_int();
-        let selected = decrypt.read_int();
-        let mut bags = Vec::with_capacity(num_bags as usize);
+        let num_bags = decrypt.read_int()?;
+        let focused = decrypt.read_int()?;
+        let selected = decrypt.read_int()?;
+        let mut bags = Vec::with_capacity(decrypt.capacity_for(num_bags, 4));
         for _ in 0..num_bags {
-            bags.push(Bag::read(decrypt));
+            bags.push(Bag::read(decrypt)?);
         }
-        let use_alternate = decrypt.read_byte();
-        let equipment = std::array::from_fn(|_| InventoryEquipment::read(decrypt));
-        let alternate_1 = decrypt.read_byte();
-        let weapon_set_1 = std::array::from_fn(|_| InventoryEquipment::read(decrypt));
-        let alternate_2 = decrypt.read_byte();
-        let weapon_set_2 = std::array::from_fn(|_| InventoryEquipment::read(decrypt));
+        let use_alternate = decrypt.read_byte()?;
+        let equipment = read_equipment(decrypt)?;
+        let alternate_1 = decrypt.read_byte()?;
+        let weapon_set_1 = read_equipment(decrypt)?;
+        let alternate_2 = decrypt.read_byte()?;
+        let weapon_set_2 = read_equipment(decrypt)?;
 
         let ret = Self {
-            num_bags,
             bags,
             equipment,
             weapon_set_1,
@@ -123,54 +193,155 @@ impl Inventory {
             alternate_1,
             alternate_2,
         };
-        decrypt.read_block_end(&block).unwrap();
-        ret
+        decrypt.read_block_end(&block)?;
+        Ok(ret)
+    }
+
+    /// Which weapon set the character has in hand, 0 or 1. Each set is flagged whether it's the alternate one, and
+    /// use_alternate says whether the alternate set is out.
+    pub fn active_weapon_set(&self) -> usize {
+        let use_alternate = self.use_alternate != 0;
+        [self.alternate_1, self.alternate_2]
+            .iter()
+            .position(|&alternate| (alternate != 0) == use_alternate)
+            .unwrap_or(use_alternate as usize)
+    }
+
+    fn write(&self, encrypt: &mut Encrypt) {
+        let block = encrypt.write_block_start(3);
+        encrypt.write_int(4);
+        encrypt.write_byte(self.flag);
+        encrypt.write_int(self.bags.len() as u32);
+        encrypt.write_int(self.focused);
+        encrypt.write_int(self.selected);
+        for bag in &self.bags {
+            bag.write(encrypt);
+        }
+        encrypt.write_byte(self.use_alternate);
+        write_equipment(encrypt, &self.equipment);
+        encrypt.write_byte(self.alternate_1);
+        write_equipment(encrypt, &self.weapon_set_1);
+        encrypt.write_byte(self.alternate_2);
+        write_equipment(encrypt, &self.weapon_set_2);
+        encrypt.write_block_end(block);
+    }
+}
+
+fn read_equipment<const N: usize>(decrypt: &mut Decrypt) -> Result<[InventoryEquipment; N], GdError> {
+    let mut equipment = Vec::with_capacity(N);
+    for _ in 0..N {
+        equipment.push(InventoryEquipment::read(decrypt)?);
+    }
+    Ok(equipment.try_into().unwrap_or_else(|_| unreachable!("read exactly N items")))
+}
+
+fn write_equipment(encrypt: &mut Encrypt, equipment: &[InventoryEquipment]) {
+    for slot in equipment {
+        slot.write(encrypt);
     }
 }
 
 #[derive(Debug)]
-struct PlayerHeader {
-    name: String,
-    _sex: bool, // which is which?
-    _class_tag: String,
-    _level: u32,
-    _hardcore: bool, // reversed?
+pub struct PlayerHeader {
+    pub name: String,
+    pub sex: bool, // which is which?
+    pub class_tag: String,
+    pub level: u32,
+    pub hardcore: bool,
+    pub expansion_status: u8,
 }
 
-// this is here just so it's decrypted correctly, simply reading sizeof() bytes didn't work somewhy
+// simply reading sizeof() bytes didn't work somewhy
+#[derive(Debug)]
 #[allow(dead_code)]
-struct CharacterInfo {
-    is_in_main_quest: u8,
-    has_been_in_game: u8,
-    difficulty: u8,
-    greatest_difficulty: u8,
-    money: u32,
-    greatest_survival_difficulty: u8,
-    current_tribute: u32,
-    compass_state: u8,
-    skill_window_show_help: u8,
-    weapon_swap_active: u8,
-    weapon_swap_enabled: u8,
-    texture: String,
-    loot_filter_len: u32,
-    loot_filter: [u8; 39],
-}
-
-fn skip_block_with_size_n(decrypt: &mut Decrypt, expected_start: u32, version: u32, size: usize) {
-    let (start, block) = decrypt.read_block_start();
-    assert_eq!(start, expected_start);
-    assert_eq!(decrypt.read_int(), version);
-    for _ in 0..size {
-        decrypt.read_byte();
-    }
-    decrypt.read_block_end(&block).unwrap();
+pub struct CharacterInfo {
+    pub is_in_main_quest: u8,
+    pub has_been_in_game: u8,
+    pub difficulty: u8,
+    pub greatest_difficulty: u8,
+    pub money: u32,
+    pub greatest_survival_difficulty: u8,
+    pub current_tribute: u32,
+    pub compass_state: u8,
+    pub skill_window_show_help: u8,
+    pub weapon_swap_active: u8,
+    pub weapon_swap_enabled: u8,
+    pub texture: String,
+    pub loot_filter_len: u32,
+    pub loot_filter: [u8; 39],
+}
+
+/// Level, experience and attributes, as on the character screen in game.
+#[derive(Clone, Debug)]
+pub struct CharacterBio {
+    pub level: u32,
+    pub experience: u32,
+    /// Unspent points
+    pub attribute_points: u32,
+    pub skill_points: u32,
+    pub devotion_points: u32,
+    /// Devotion points earned so far, spent or not
+    pub total_devotion: u32,
+    pub physique: f32,
+    pub cunning: f32,
+    pub spirit: f32,
+    pub health: f32,
+    pub energy: f32,
+}
+
+impl CharacterBio {
+    fn read(decrypt: &mut Decrypt) -> Result<Self, GdError> {
+        let block = decrypt.read_block_start(2)?;
+        decrypt.expect_int("character bio version", 8)?;
+        let ret = Self {
+            level: decrypt.read_int()?,
+            experience: decrypt.read_int()?,
+            attribute_points: decrypt.read_int()?,
+            skill_points: decrypt.read_int()?,
+            devotion_points: decrypt.read_int()?,
+            total_devotion: decrypt.read_int()?,
+            physique: decrypt.read_float()?,
+            cunning: decrypt.read_float()?,
+            spirit: decrypt.read_float()?,
+            health: decrypt.read_float()?,
+            energy: decrypt.read_float()?,
+        };
+        decrypt.read_block_end(&block)?;
+        Ok(ret)
+    }
+
+    fn write(&self, encrypt: &mut Encrypt) {
+        let block = encrypt.write_block_start(2);
+        encrypt.write_int(8);
+        encrypt.write_int(self.level);
+        encrypt.write_int(self.experience);
+        encrypt.write_int(self.attribute_points);
+        encrypt.write_int(self.skill_points);
+        encrypt.write_int(self.devotion_points);
+        encrypt.write_int(self.total_devotion);
+        encrypt.write_float(self.physique);
+        encrypt.write_float(self.cunning);
+        encrypt.write_float(self.spirit);
+        encrypt.write_float(self.health);
+        encrypt.write_float(self.energy);
+        encrypt.write_block_end(block);
+    }
+}
+
+/// Name of a difficulty in CharacterInfo.
+pub fn difficulty_name(difficulty: u8) -> &'static str {
+    match difficulty {
+        0 => "Normal",
+        1 => "Elite",
+        2 => "Ultimate",
+        _ => "Unknown",
+    }
 }
 
 impl CharacterInfo {
-    fn read(decrypt: &mut Decrypt) -> Self {
-        let (start, block) = decrypt.read_block_start();
-        assert_eq!(start, 1);
-        assert_eq!(decrypt.read_int(), 5); // version == 5
+    fn read(decrypt: &mut Decrypt) -> Result<Self, GdError> {
+        let block = decrypt.read_block_start(1)?;
+        decrypt.expect_int("character info version", 5)?;
         //let texture = decrypt.read_str();
         //let _skipped_ints = size_of::<u32>() * 41;
         //for _ in 0..size_of::<CharacterInfo>() {
@@ -178,74 +349,161 @@ impl CharacterInfo {
         //}
 
         let ret = Self {
-            is_in_main_quest: decrypt.read_byte(),
-            has_been_in_game: decrypt.read_byte(),
-            difficulty: decrypt.read_byte(),
-            greatest_difficulty: decrypt.read_byte(),
-            money: decrypt.read_int(),
-            greatest_survival_difficulty: decrypt.read_byte(),
-            current_tribute: decrypt.read_int(),
-            compass_state: decrypt.read_byte(),
-            skill_window_show_help: decrypt.read_byte(),
-            weapon_swap_active: decrypt.read_byte(),
-            weapon_swap_enabled: decrypt.read_byte(),
-            texture: decrypt.read_str().unwrap(),
-            loot_filter_len: decrypt.read_int(),
+            is_in_main_quest: decrypt.read_byte()?,
+            has_been_in_game: decrypt.read_byte()?,
+            difficulty: decrypt.read_byte()?,
+            greatest_difficulty: decrypt.read_byte()?,
+            money: decrypt.read_int()?,
+            greatest_survival_difficulty: decrypt.read_byte()?,
+            current_tribute: decrypt.read_int()?,
+            compass_state: decrypt.read_byte()?,
+            skill_window_show_help: decrypt.read_byte()?,
+            weapon_swap_active: decrypt.read_byte()?,
+            weapon_swap_enabled: decrypt.read_byte()?,
+            texture: decrypt.read_str()?,
+            loot_filter_len: decrypt.read_int()?,
             loot_filter: {
                 let mut buf = [0; 39];
                 for byte in buf.iter_mut() {
-                    *byte = decrypt.read_byte();
+                    *byte = decrypt.read_byte()?;
                 }
                 buf
             },
         };
-        decrypt.read_block_end(&block).unwrap();
-        ret
+        decrypt.read_block_end(&block)?;
+        Ok(ret)
+    }
+
+    fn write(&self, encrypt: &mut Encrypt) {
+        let block = encrypt.write_block_start(1);
+        encrypt.write_int(5);
+        encrypt.write_byte(self.is_in_main_quest);
+        encrypt.write_byte(self.has_been_in_game);
+        encrypt.write_byte(self.difficulty);
+        encrypt.write_byte(self.greatest_difficulty);
+        encrypt.write_int(self.money);
+        encrypt.write_byte(self.greatest_survival_difficulty);
+        encrypt.write_int(self.current_tribute);
+        encrypt.write_byte(self.compass_state);
+        encrypt.write_byte(self.skill_window_show_help);
+        encrypt.write_byte(self.weapon_swap_active);
+        encrypt.write_byte(self.weapon_swap_enabled);
+        encrypt.write_str(&self.texture);
+        encrypt.write_int(self.loot_filter_len);
+        for &byte in &self.loot_filter {
+            encrypt.write_byte(byte);
+        }
+        encrypt.write_block_end(block);
     }
 }
 
 impl PlayerHeader {
-    fn read(decrypt: &mut Decrypt) -> Self {
-        Self {
-            name: decrypt.read_wide_string().unwrap(),
-            _sex: decrypt.read_bool(),
-            _class_tag: decrypt.read_str().unwrap(),
-            _level: decrypt.read_int(),
-            _hardcore: decrypt.read_bool(),
-        }
+    fn read(decrypt: &mut Decrypt) -> Result<Self, GdError> {
+        Ok(Self {
+            name: decrypt.read_wide_string()?,
+            sex: decrypt.read_bool()?,
+            class_tag: decrypt.read_str()?,
+            level: decrypt.read_int()?,
+            hardcore: decrypt.read_bool()?,
+            expansion_status: decrypt.read_byte()?,
+        })
+    }
+
+    fn write(&self, encrypt: &mut Encrypt) {
+        encrypt.write_wide_string(&self.name);
+        encrypt.write_bool(self.sex);
+        encrypt.write_str(&self.class_tag);
+        encrypt.write_int(self.level);
+        encrypt.write_bool(self.hardcore);
+        encrypt.write_byte(self.expansion_status);
     }
 }
 
 pub struct CharacterItems {
-    pub name: String,
+    initial_key: u32,
+    pub header: PlayerHeader,
+    uid: [u8; 16],
+    pub info: CharacterInfo,
+    pub bio: CharacterBio,
     pub inventory: Inventory,
     pub stash: PlayerStash,
+    /// Skills, quests, factions and the rest, which gdlc doesn't decode
+    pub other_blocks: Vec<RawBlock>,
 }
 
 impl CharacterItems {
-    pub fn read(path: &PathBuf) -> Result<Self, Error> {
-        let mut decrypt = Decrypt::new(path)?;
-        assert_eq!(decrypt.read_int(), 0x58434447);
-        assert_eq!(decrypt.read_int(), 2);
-        let header = PlayerHeader::read(&mut decrypt);
-        let _byte = decrypt.read_byte();
-        assert_eq!(decrypt.next_int(), 0); // end of block
-        assert_eq!(decrypt.read_int(), 8); // version
-
-        let mut uid_buf: [u8; 16] = [0; 16];
-        for byte in uid_buf.iter_mut() {
-            *byte = decrypt.read_byte();
+    pub fn read(path: &PathBuf) -> Result<Self, GdError> {
+        Self::read_decrypted(Decrypt::new(path)?)
+    }
+
+    /// Read a character file that is already in memory.
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<Self, GdError> {
+        Self::read_decrypted(Decrypt::from_bytes(bytes)?)
+    }
+
+    fn read_decrypted(mut decrypt: Decrypt) -> Result<Self, GdError> {
+        decrypt.expect_int("character file magic", 0x58434447)?;
+        decrypt.expect_int("character header version", 2)?;
+        let header = PlayerHeader::read(&mut decrypt)?;
+        decrypt.expect_next_int("end of header block", 0)?;
+        decrypt.expect_int("character file version", 8)?;
+
+        let mut uid: [u8; 16] = [0; 16];
+        for byte in uid.iter_mut() {
+            *byte = decrypt.read_byte()?;
         }
-        let _char_info = CharacterInfo::read(&mut decrypt);
-        //skip_character_bio(&mut decrypt);
-        skip_block_with_size_n(&mut decrypt, 2, 8, 44); // skip bio
-        let inventory = Inventory::read(&mut decrypt);
+        let info = CharacterInfo::read(&mut decrypt)?;
+        let bio = CharacterBio::read(&mut decrypt)?;
+        let inventory = Inventory::read(&mut decrypt)?;
         let stash = PlayerStash::read(&mut decrypt)?;
+        let mut other_blocks = Vec::new();
+        while decrypt.remaining() > 0 {
+            other_blocks.push(decrypt.read_raw_block()?);
+        }
 
         Ok(Self {
-            name: header.name,
+            initial_key: decrypt.initial_key(),
+            header,
+            uid,
+            info,
+            bio,
             inventory,
             stash,
+            other_blocks,
         })
     }
+
+    /// Encrypt the character as the game would. Unchanged characters come out byte for byte as they were read.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut encrypt = Encrypt::new(self.initial_key);
+        encrypt.write_int(0x58434447);
+        encrypt.write_int(2);
+        self.header.write(&mut encrypt);
+        encrypt.write_next_int(0);
+        encrypt.write_int(8);
+        for &byte in &self.uid {
+            encrypt.write_byte(byte);
+        }
+        self.info.write(&mut encrypt);
+        self.bio.write(&mut encrypt);
+        self.inventory.write(&mut encrypt);
+        self.stash.write(&mut encrypt);
+        for raw_block in &self.other_blocks {
+            encrypt.write_raw_block(raw_block);
+        }
+        encrypt.into_bytes()
+    }
+
+    /// Write the save file, replacing any old one.
+    pub fn write(&self, path: &Path) -> Result<(), GdError> {
+        encrypt::replace_file(path, &self.to_bytes())
+    }
+
+    /// Everything the character has: bags, personal stash and equipped items.
+    pub fn items(&self) -> impl Iterator<Item = &InventoryItem> {
+        let bags = self.inventory.bags.iter().flat_map(Bag::items);
+        let inventory = &self.inventory;
+        let equipped = inventory.equipment.iter().chain(&inventory.weapon_set_1).chain(&inventory.weapon_set_2);
+        bags.chain(self.stash.tabs.iter().flat_map(StashTab::items)).chain(equipped.map(|equipment| &equipment.item))
+    }
 }
@@ -0,0 +1,616 @@
+use crate::fuzzy;
+use crate::item_category::ItemCategory;
+use crate::item_location::ItemLocation;
+use crate::item_search::CompleteItem;
+use crate::item_search::Rarity;
+
+use std::fmt;
+use std::fmt::Display;
+
+use regex::Regex;
+use regex::RegexBuilder;
+
+/// A parsed search, eg. `rarity:>=epic type:ring lvl:>70 NOT loc:equipped`.
+///
+/// Terms next to each other must all match, `OR` matches either side and `NOT` the opposite, with parentheses to
+/// group. A term is a word or "quoted phrase" found in the item's full name, a /regex/, or a `field:value`.
+#[derive(Debug, Default)]
+pub struct Query {
+    /// None matches everything
+    expr: Option<Expr>,
+}
+
+#[derive(Debug)]
+enum Expr {
+    And(Vec<Expr>),
+    Or(Vec<Expr>),
+    Not(Box<Expr>),
+    Term(Term),
+}
+
+#[derive(Debug)]
+enum Term {
+    Text(TextField, TextMatch),
+    Rarity(Comparison, Rarity),
+    Level(Comparison, u32),
+    Quantity(Comparison, u32),
+    /// An ItemCategory name, or an equipment slot that takes several, see ItemCategory::slot()
+    Category(&'static str),
+    /// Lower case
+    Character(String),
+    Location(LocationKind),
+}
+
+#[derive(Clone, Copy, Debug)]
+enum TextField {
+    /// Prefix, name and suffix
+    FullName,
+    Name,
+    Prefix,
+    Suffix,
+}
+
+#[derive(Debug)]
+enum TextMatch {
+    /// Lower case
+    Contains(String),
+    /// Lower case words, each of which may have a few typos
+    Fuzzy(Vec<String>),
+    Regex(Regex),
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Comparison {
+    Equal,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum LocationKind {
+    Shared,
+    Stash,
+    Inventory,
+    Equipped,
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+    Term(String),
+}
+
+impl Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::And => write!(f, "AND"),
+            Self::Or => write!(f, "OR"),
+            Self::Not => write!(f, "NOT"),
+            Self::Open => write!(f, "("),
+            Self::Close => write!(f, ")"),
+            Self::Term(text) => write!(f, "{text}"),
+        }
+    }
+}
+
+impl Query {
+    /// The arguments as the shell split them. An argument with spaces in it was quoted, and is taken as one term,
+    /// eg. `prefix:"of the kraken"`, unless it's a whole search with operators, parentheses or several fields.
+    pub fn parse(args: &[String]) -> Result<Self, String> {
+        tokenize(args).and_then(Self::parse_tokens).map_err(|error| format!("Invalid search: {error}"))
+    }
+
+    fn parse_tokens(tokens: Vec<Token>) -> Result<Self, String> {
+        if tokens.is_empty() {
+            return Ok(Self::default());
+        }
+        let mut parser = Parser { tokens, pos: 0 };
+        let expr = parser.or()?;
+        match parser.tokens.get(parser.pos) {
+            None => Ok(Self { expr: Some(expr) }),
+            Some(Token::Close) => Err("unmatched ) in the search".to_string()),
+            Some(token) => Err(format!("unexpected {token} in the search")),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.expr.is_none()
+    }
+
+    /// Let words and phrases match with a few typos, see score().
+    pub fn fuzzy(mut self) -> Self {
+        if let Some(expr) = &mut self.expr {
+            expr.make_fuzzy();
+        }
+        self
+    }
+
+    pub fn matches(&self, item: &CompleteItem, location: &ItemLocation) -> bool {
+        self.score(item, location).is_some()
+    }
+
+    /// How many typos it took to match, for ranking fuzzy results. None if the item doesn't match, 0 if it matched
+    /// as written.
+    pub fn score(&self, item: &CompleteItem, location: &ItemLocation) -> Option<u32> {
+        self.expr.as_ref().map_or(Some(0), |expr| expr.score(item, location))
+    }
+
+    /// The words searched for in item names, leaving out the ones after NOT. For suggesting names.
+    pub fn words(&self) -> Vec<String> {
+        let mut words = Vec::new();
+        if let Some(expr) = &self.expr {
+            expr.collect_words(&mut words);
+        }
+        words
+    }
+}
+
+impl Expr {
+    fn score(&self, item: &CompleteItem, location: &ItemLocation) -> Option<u32> {
+        match self {
+            Self::And(exprs) => exprs.iter().map(|expr| expr.score(item, location)).sum(),
+            Self::Or(exprs) => exprs.iter().filter_map(|expr| expr.score(item, location)).min(),
+            Self::Not(expr) => expr.score(item, location).is_none().then_some(0),
+            Self::Term(term) => term.score(item, location),
+        }
+    }
+
+    fn make_fuzzy(&mut self) {
+        match self {
+            Self::And(exprs) | Self::Or(exprs) => exprs.iter_mut().for_each(Self::make_fuzzy),
+            Self::Not(expr) => expr.make_fuzzy(),
+            Self::Term(Term::Text(_, text_match)) => {
+                if let TextMatch::Contains(text) = text_match {
+                    *text_match = TextMatch::Fuzzy(text.split_whitespace().map(str::to_string).collect());
+                }
+            }
+            Self::Term(_) => (),
+        }
+    }
+
+    fn collect_words(&self, words: &mut Vec<String>) {
+        match self {
+            Self::And(exprs) | Self::Or(exprs) => exprs.iter().for_each(|expr| expr.collect_words(words)),
+            Self::Not(_) => (),
+            Self::Term(Term::Text(_, TextMatch::Contains(text))) => {
+                words.extend(text.split_whitespace().map(str::to_string));
+            }
+            Self::Term(Term::Text(_, TextMatch::Fuzzy(fuzzy_words))) => words.extend(fuzzy_words.iter().cloned()),
+            Self::Term(_) => (),
+        }
+    }
+}
+
+impl Term {
+    fn score(&self, item: &CompleteItem, location: &ItemLocation) -> Option<u32> {
+        let Self::Text(field, text_match) = self else {
+            return self.matches(item, location).then_some(0);
+        };
+        let text = match field {
+            TextField::FullName => Some(item.full_name()),
+            TextField::Name => Some(item.name().to_string()),
+            TextField::Prefix => item.prefix().map(str::to_string),
+            TextField::Suffix => item.suffix().map(str::to_string),
+        };
+        text_match.score(&text?)
+    }
+
+    /// For the terms that match or don't, without typos.
+    fn matches(&self, item: &CompleteItem, location: &ItemLocation) -> bool {
+        match self {
+            Self::Text(..) => self.score(item, location).is_some(),
+            Self::Rarity(comparison, rarity) => comparison.compare(item.rarity(), *rarity),
+            // Items without a level requirement can be used at level 1, or 0 for all it matters
+            Self::Level(comparison, level) => comparison.compare(item.level_req().unwrap_or(0), *level),
+            Self::Quantity(comparison, quantity) => comparison.compare(item.quantity(), *quantity),
+            Self::Category(name) => item.category().name() == *name || item.category().slot() == Some(name),
+            Self::Character(name) => location.character().is_some_and(|character| character.to_lowercase() == *name),
+            Self::Location(kind) => match location {
+                ItemLocation::SharedStash { .. } => *kind == LocationKind::Shared,
+                ItemLocation::CharacterStash { .. } => *kind == LocationKind::Stash,
+                ItemLocation::Bag { .. } => *kind == LocationKind::Inventory,
+                ItemLocation::Equipped { .. } | ItemLocation::WeaponSet { .. } => *kind == LocationKind::Equipped,
+            },
+        }
+    }
+}
+
+impl TextMatch {
+    fn parse(value: &str) -> Result<Self, String> {
+        match value.strip_prefix('/').and_then(|value| value.strip_suffix('/')) {
+            Some(pattern) => match RegexBuilder::new(pattern).case_insensitive(true).build() {
+                Ok(regex) => Ok(Self::Regex(regex)),
+                // The message draws the pattern with a marker under the mistake, its last line says what it is
+                Err(e) => {
+                    let error = e.to_string();
+                    let reason = error.lines().last().unwrap_or_default().trim_start_matches("error: ");
+                    Err(format!("/{pattern}/ is not a valid regex: {reason}"))
+                }
+            },
+            None => Ok(Self::Contains(unquote(value).to_lowercase())),
+        }
+    }
+
+    fn score(&self, text: &str) -> Option<u32> {
+        match self {
+            Self::Contains(part) => text.to_lowercase().contains(part.as_str()).then_some(0),
+            Self::Fuzzy(words) => fuzzy::score(words, text),
+            Self::Regex(regex) => regex.is_match(text).then_some(0),
+        }
+    }
+}
+
+impl Comparison {
+    /// The comparison at the start of the value, eg. ">=" in ">=90", and the rest.
+    fn parse(value: &str) -> (Self, &str) {
+        for (operator, comparison) in [
+            (">=", Self::GreaterOrEqual),
+            ("<=", Self::LessOrEqual),
+            (">", Self::Greater),
+            ("<", Self::Less),
+            ("=", Self::Equal),
+        ] {
+            if let Some(rest) = value.strip_prefix(operator) {
+                return (comparison, rest);
+            }
+        }
+        (Self::Equal, value)
+    }
+
+    fn compare<T: Ord>(self, value: T, other: T) -> bool {
+        match self {
+            Self::Equal => value == other,
+            Self::Less => value < other,
+            Self::LessOrEqual => value <= other,
+            Self::Greater => value > other,
+            Self::GreaterOrEqual => value >= other,
+        }
+    }
+}
+
+/// A category name, or where items are equipped, eg. "weapon" or "off-hand" for shields too.
+fn parse_category(value: &str) -> Result<&'static str, String> {
+    let value = match value {
+        "necklace" => "amulet",
+        "torso" | "armor" => "chest",
+        "shoulder" => "shoulders",
+        "gloves" => "hands",
+        "boots" => "feet",
+        "helm" => "head",
+        "waist" => "belt",
+        "offhand" | "focus" => "off-hand",
+        "quest" => "quest_item",
+        value => value,
+    };
+    let known = |value: &str| value == "weapon" || ItemCategory::parse(value).is_some();
+    let value = value.strip_suffix('s').filter(|singular| known(singular)).unwrap_or(value);
+    match ItemCategory::parse(value) {
+        Some(category) => Ok(category.name()),
+        None if value == "weapon" => Ok("weapon"),
+        None => {
+            let names: Vec<&str> = ItemCategory::ALL.iter().map(ItemCategory::name).collect();
+            Err(format!("unknown type {value}, it can be weapon or one of {}", names.join(", ")))
+        }
+    }
+}
+
+fn parse_term(text: &str) -> Result<Term, String> {
+    let Some((field, value)) = text.split_once(':').filter(|(field, _)| field.chars().all(|c| c.is_ascii_alphabetic()))
+    else {
+        return Ok(Term::Text(TextField::FullName, TextMatch::parse(text)?));
+    };
+    let number = |value: &str| {
+        let (comparison, number) = Comparison::parse(value);
+        match number.parse() {
+            Ok(number) => Ok((comparison, number)),
+            Err(_) => Err(format!("{field}: needs a number, eg. {field}:>=90")),
+        }
+    };
+    let lower_value = unquote(value).to_lowercase();
+    Ok(match field.to_lowercase().as_str() {
+        "name" => Term::Text(TextField::Name, TextMatch::parse(value)?),
+        "prefix" => Term::Text(TextField::Prefix, TextMatch::parse(value)?),
+        "suffix" => Term::Text(TextField::Suffix, TextMatch::parse(value)?),
+        "rarity" => {
+            let (comparison, rarity) = Comparison::parse(&lower_value);
+            let rarity = Rarity::parse(rarity).ok_or_else(|| {
+                let names: Vec<&str> = Rarity::ALL.iter().map(Rarity::name).collect();
+                format!("unknown rarity {rarity}, it can be one of {}", names.join(", "))
+            })?;
+            Term::Rarity(comparison, rarity)
+        }
+        "lvl" | "level" => {
+            let (comparison, level) = number(value)?;
+            Term::Level(comparison, level)
+        }
+        "qty" | "quantity" => {
+            let (comparison, quantity) = number(value)?;
+            Term::Quantity(comparison, quantity)
+        }
+        "type" | "slot" => Term::Category(parse_category(&lower_value)?),
+        "char" | "character" => Term::Character(lower_value),
+        "loc" | "location" => Term::Location(match lower_value.as_str() {
+            "shared" => LocationKind::Shared,
+            "stash" => LocationKind::Stash,
+            "bag" | "inventory" => LocationKind::Inventory,
+            "equipped" => LocationKind::Equipped,
+            _ => return Err(format!("unknown location {lower_value}, it can be shared, stash, inventory or equipped")),
+        }),
+        _ => {
+            return Err(format!(
+                "unknown field {field}:, it can be name, prefix, suffix, rarity, lvl, qty, type, char or loc"
+            ));
+        }
+    })
+}
+
+fn unquote(value: &str) -> &str {
+    value.strip_prefix('"').and_then(|value| value.strip_suffix('"')).unwrap_or(value)
+}
+
+/// Whether an argument with spaces was quoted as a phrase, rather than being a search of its own, eg. `ring OR amulet`
+/// or `type:ring lvl:>90`.
+fn is_phrase(arg: &str) -> bool {
+    let is_field = |word: &str| {
+        word.split_once(':')
+            .is_some_and(|(field, _)| !field.is_empty() && field.chars().all(|c| c.is_ascii_alphabetic()))
+    };
+    arg.contains(char::is_whitespace)
+        && !arg.contains(['"', '(', ')'])
+        && !arg.split_whitespace().any(|word| matches!(word, "AND" | "OR" | "NOT"))
+        && !arg.split_whitespace().skip(1).any(is_field)
+}
+
+fn tokenize(args: &[String]) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    for arg in args {
+        // The shell already took the quotes off, the spaces say it was one phrase
+        if is_phrase(arg) {
+            tokens.push(Token::Term(arg.trim().to_string()));
+            continue;
+        }
+        let chars: Vec<char> = arg.chars().collect();
+        let mut i = 0;
+        while i < chars.len() {
+            match chars[i] {
+                c if c.is_whitespace() => i += 1,
+                '(' => {
+                    tokens.push(Token::Open);
+                    i += 1;
+                }
+                ')' => {
+                    tokens.push(Token::Close);
+                    i += 1;
+                }
+                _ => {
+                    let start = i;
+                    while i < chars.len() && !chars[i].is_whitespace() && chars[i] != ')' {
+                        // Phrases and regexes go on to their closing quote or slash, whatever is in between
+                        let closing = match chars[i] {
+                            '"' => '"',
+                            '/' if i == start || chars[i - 1] == ':' => '/',
+                            _ => {
+                                i += 1;
+                                continue;
+                            }
+                        };
+                        let mut end = i + 1;
+                        while end < chars.len() && chars[end] != closing {
+                            end += if chars[end] == '\\' { 2 } else { 1 };
+                        }
+                        if end >= chars.len() {
+                            return Err(format!("unmatched {closing} in the search"));
+                        }
+                        i = end + 1;
+                    }
+                    let word: String = chars[start..i].iter().collect();
+                    tokens.push(match word.as_str() {
+                        "AND" => Token::And,
+                        "OR" => Token::Or,
+                        "NOT" => Token::Not,
+                        _ => Token::Term(word),
+                    });
+                }
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn or(&mut self) -> Result<Expr, String> {
+        let mut exprs = vec![self.and()?];
+        while self.tokens.get(self.pos) == Some(&Token::Or) {
+            self.pos += 1;
+            exprs.push(self.and()?);
+        }
+        Ok(if exprs.len() == 1 {
+            exprs.remove(0)
+        } else {
+            Expr::Or(exprs)
+        })
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        let mut exprs = vec![self.not()?];
+        loop {
+            match self.tokens.get(self.pos) {
+                None | Some(Token::Or) | Some(Token::Close) => break,
+                Some(Token::And) => self.pos += 1,
+                _ => (),
+            }
+            exprs.push(self.not()?);
+        }
+        Ok(if exprs.len() == 1 {
+            exprs.remove(0)
+        } else {
+            Expr::And(exprs)
+        })
+    }
+
+    fn not(&mut self) -> Result<Expr, String> {
+        if self.tokens.get(self.pos) == Some(&Token::Not) {
+            self.pos += 1;
+            return Ok(Expr::Not(Box::new(self.not()?)));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Expr, String> {
+        let token = self.tokens.get(self.pos).ok_or("the search ends with an operator")?;
+        self.pos += 1;
+        match token {
+            Token::Open => {
+                let expr = self.or()?;
+                if self.tokens.get(self.pos) != Some(&Token::Close) {
+                    return Err("unmatched ( in the search".to_string());
+                }
+                self.pos += 1;
+                Ok(expr)
+            }
+            Token::Term(text) => parse_term(text).map(Expr::Term),
+            Token::And | Token::Or | Token::Not | Token::Close => {
+                Err(format!("expected a search term instead of {token}"))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The parsed search with explicit parentheses, eg. "((ring AND blue) OR NOT red)".
+    fn parse(args: &[&str]) -> Result<String, String> {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        Query::parse(&args).map(|query| query.expr.as_ref().map_or_else(String::new, show))
+    }
+
+    fn show(expr: &Expr) -> String {
+        let join = |exprs: &[Expr], operator: &str| {
+            let exprs: Vec<String> = exprs.iter().map(show).collect();
+            format!("({})", exprs.join(operator))
+        };
+        match expr {
+            Expr::And(exprs) => join(exprs, " AND "),
+            Expr::Or(exprs) => join(exprs, " OR "),
+            Expr::Not(expr) => format!("NOT {}", show(expr)),
+            Expr::Term(Term::Text(TextField::FullName, TextMatch::Contains(text))) => text.clone(),
+            Expr::Term(term) => format!("{term:?}"),
+        }
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!(parse(&["ring", "blue", "OR", "red"]).unwrap(), "((ring AND blue) OR red)");
+        assert_eq!(parse(&["ring", "OR", "blue", "AND", "red"]).unwrap(), "(ring OR (blue AND red))");
+        assert_eq!(parse(&["NOT", "ring", "blue"]).unwrap(), "(NOT ring AND blue)");
+        assert_eq!(parse(&["NOT", "NOT", "ring"]).unwrap(), "NOT NOT ring");
+        assert_eq!(parse(&["(ring", "OR", "blue)", "red"]).unwrap(), "((ring OR blue) AND red)");
+        assert_eq!(parse(&["ring", "NOT", "(blue", "OR", "red)"]).unwrap(), "(ring AND NOT (blue OR red))");
+        // The operators are upper case, otherwise they are words
+        assert_eq!(parse(&["ring", "or", "blue"]).unwrap(), "(ring AND or AND blue)");
+        // The whole search quoted as one argument
+        assert_eq!(parse(&["ring OR (blue red)"]).unwrap(), "(ring OR (blue AND red))");
+        assert_eq!(
+            parse(&["rarity:>=epic type:ring NOT loc:equipped"]).unwrap(),
+            r#"(Rarity(GreaterOrEqual, Epic) AND Category("ring") AND NOT Location(Equipped))"#
+        );
+        assert_eq!(parse(&[]).unwrap(), "");
+    }
+
+    #[test]
+    fn quoted_phrases() {
+        let kraken = r#"Text(Prefix, Contains("of the kraken"))"#;
+        // prefix:"of the kraken" after the shell took the quotes off
+        assert_eq!(parse(&["prefix:of the kraken"]).unwrap(), kraken);
+        // '"prefix:"of the kraken"' with the quotes still in it
+        assert_eq!(parse(&[r#"prefix:"Of The Kraken""#]).unwrap(), kraken);
+        assert_eq!(parse(&[r#"prefix:"of the kraken" OR ring"#]).unwrap(), format!("({kraken} OR ring)"));
+        assert_eq!(parse(&[r#""of the kraken""#, "ring"]).unwrap(), "(of the kraken AND ring)");
+        assert_eq!(parse(&["of the kraken", "ring"]).unwrap(), "(of the kraken AND ring)");
+    }
+
+    #[test]
+    fn fields() {
+        assert_eq!(parse(&["lvl:>=90"]).unwrap(), "Level(GreaterOrEqual, 90)");
+        assert_eq!(parse(&["qty:<5"]).unwrap(), "Quantity(Less, 5)");
+        assert_eq!(parse(&["level:90"]).unwrap(), "Level(Equal, 90)");
+        assert_eq!(parse(&["rarity:>epic"]).unwrap(), "Rarity(Greater, Epic)");
+        assert_eq!(parse(&["type:Rings"]).unwrap(), r#"Category("ring")"#);
+        assert_eq!(parse(&["slot:focus"]).unwrap(), r#"Category("off-hand")"#);
+        assert_eq!(parse(&["loc:bag"]).unwrap(), "Location(Inventory)");
+        assert_eq!(parse(&["char:Alina"]).unwrap(), r#"Character("alina")"#);
+        // Not a field name, just a word with a colon in it
+        assert_eq!(parse(&["d005:necklace"]).unwrap(), "d005:necklace");
+        assert!(parse(&["name:/^mythical/"]).unwrap().starts_with("Text(Name, Regex("));
+        assert!(parse(&["/^mythical .* of valor$/"]).unwrap().starts_with("Text(FullName, Regex("));
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(parse(&["(ring"]).unwrap_err(), "Invalid search: unmatched ( in the search");
+        assert_eq!(parse(&["ring)"]).unwrap_err(), "Invalid search: unmatched ) in the search");
+        assert_eq!(parse(&[r#""of the kraken"#]).unwrap_err(), r#"Invalid search: unmatched " in the search"#);
+        assert_eq!(parse(&["/ring"]).unwrap_err(), "Invalid search: unmatched / in the search");
+        assert_eq!(parse(&["name:/ring"]).unwrap_err(), "Invalid search: unmatched / in the search");
+        assert_eq!(parse(&["ring", "OR"]).unwrap_err(), "Invalid search: the search ends with an operator");
+        assert_eq!(parse(&["OR", "ring"]).unwrap_err(), "Invalid search: expected a search term instead of OR");
+        assert_eq!(parse(&["lvl:high"]).unwrap_err(), "Invalid search: lvl: needs a number, eg. lvl:>=90");
+        assert!(parse(&["rarity:shiny"]).unwrap_err().starts_with("Invalid search: unknown rarity shiny"));
+        assert!(parse(&["type:hat"]).unwrap_err().starts_with("Invalid search: unknown type hat"));
+        assert!(parse(&["colour:red"]).unwrap_err().starts_with("Invalid search: unknown field colour:"));
+        assert!(parse(&["/(ring/"]).is_err());
+    }
+
+    fn regex_matches(pattern: &str, text: &str) -> bool {
+        TextMatch::parse(&format!("/{pattern}/")).unwrap().score(text).is_some()
+    }
+
+    #[test]
+    fn regexes() {
+        assert!(regex_matches("^mythical .* of valor$", "Mythical Ring of Valor"));
+        assert!(!regex_matches("^ring", "Mythical Ring"));
+        // Case insensitive, upper case ranges too
+        assert!(regex_matches("^[A-Z]+$", "abc"));
+        assert!(regex_matches(r"d\d{3}", "Mythical D005 Necklace"));
+        assert!(regex_matches("^a{2,3}$", "aaa"));
+        assert!(!regex_matches("^a{2,3}$", "aaaa"));
+        assert!(regex_matches(r"^a\{b$", "a{b"));
+        assert_eq!(TextMatch::parse("/(ring/").unwrap_err(), "/(ring/ is not a valid regex: unclosed group");
+        assert!(TextMatch::parse("/a{3,1}/").is_err());
+    }
+
+    #[test]
+    fn regexes_take_linear_time() {
+        // Backtracking would try every way of splitting the a's between the alternatives
+        let start = std::time::Instant::now();
+        let text = "a".repeat(10_000) + "b";
+        assert!(!regex_matches("^(a|a)*$", &text));
+        assert!(regex_matches("^(a|aa)*b$", &text));
+        assert!(start.elapsed() < std::time::Duration::from_secs(1));
+    }
+
+    #[test]
+    fn slashes_inside_words() {
+        // Only a slash at the start of a term or value starts a regex
+        assert_eq!(parse(&["1/2"]).unwrap(), "1/2");
+    }
+
+    #[test]
+    fn words() {
+        let args: Vec<String> = ["kraken", "NOT", "ring", "(tooth", "OR", "lvl:>5)"].map(String::from).to_vec();
+        assert_eq!(Query::parse(&args).unwrap().words(), ["kraken", "tooth"]);
+    }
+}
@@ -1,67 +1,161 @@
-use super::inventory_item::InventoryItem;
-use super::decrypt::Decrypt;
-use std::io::Error;
+use crate::decrypt::Decrypt;
+use crate::encrypt;
+use crate::encrypt::Encrypt;
+use crate::error::GdError;
+use crate::inventory_item::InventoryItem;
+
+use std::path::Path;
 use std::path::PathBuf;
 
+const STASH_VERSION: u32 = 5;
+
+/// An item and its position in a stash tab, in grid cells from the top left. Stashes store the position as floats,
+/// unlike bags.
+#[derive(Debug)]
 pub struct StashItem {
     pub item: InventoryItem,
-    _x_offset: u32,
-    _y_offset: u32,
+    pub x: f32,
+    pub y: f32,
 }
 
 impl StashItem {
-    pub fn read(decrypt: &mut Decrypt) -> Result<Self, Error> {
+    pub fn read(decrypt: &mut Decrypt) -> Result<Self, GdError> {
         Ok(Self {
             item: InventoryItem::read(decrypt)?,
-            _x_offset: decrypt.read_int(),
-            _y_offset: decrypt.read_int(),
+            x: decrypt.read_float()?,
+            y: decrypt.read_float()?,
         })
     }
+
+    pub fn write(&self, encrypt: &mut Encrypt) {
+        self.item.write(encrypt);
+        encrypt.write_float(self.x);
+        encrypt.write_float(self.y);
+    }
 }
 
+/// A tab of the shared stash or a character's stash. The size is in grid cells.
+#[derive(Debug)]
+pub struct StashTab {
+    pub width: u32,
+    pub height: u32,
+    pub items: Vec<StashItem>,
+}
+
+impl StashTab {
+    pub fn read(decrypt: &mut Decrypt) -> Result<Self, GdError> {
+        let tab_block = decrypt.read_block_start(0)?;
+        let width = decrypt.read_int()?;
+        let height = decrypt.read_int()?;
+        let item_count = decrypt.read_int()?;
+        let mut items = Vec::with_capacity(decrypt.capacity_for(item_count, 4));
+        for _ in 0..item_count {
+            items.push(StashItem::read(decrypt)?);
+        }
+        decrypt.read_block_end(&tab_block)?;
+        Ok(Self { width, height, items })
+    }
+
+    pub fn write(&self, encrypt: &mut Encrypt) {
+        let tab_block = encrypt.write_block_start(0);
+        encrypt.write_int(self.width);
+        encrypt.write_int(self.height);
+        encrypt.write_int(self.items.len() as u32);
+        for item in &self.items {
+            item.write(encrypt);
+        }
+        encrypt.write_block_end(tab_block);
+    }
+
+    pub fn items(&self) -> impl Iterator<Item = &InventoryItem> {
+        self.items.iter().map(|stash_item| &stash_item.item)
+    }
+
+    /// The first position, row by row, where an item of the given size fits. The sizes of the items already in the
+    /// tab are given in the same order as the items, as (width, height).
+    pub fn free_position(&self, size: (u32, u32), item_sizes: &[(u32, u32)]) -> Option<(u32, u32)> {
+        let (width, height) = size;
+        let occupied: Vec<_> = self
+            .items
+            .iter()
+            .zip(item_sizes)
+            .map(|(stash_item, &(w, h))| (stash_item.x as u32, stash_item.y as u32, w, h))
+            .collect();
+        let overlaps = |x: u32, y: u32| {
+            occupied.iter().any(|&(ox, oy, w, h)| x < ox + w && ox < x + width && y < oy + h && oy < y + height)
+        };
+        let max_y = self.height.checked_sub(height)?;
+        let max_x = self.width.checked_sub(width)?;
+        (0..=max_y).flat_map(|y| (0..=max_x).map(move |x| (x, y))).find(|&(x, y)| !overlaps(x, y))
+    }
+}
+
+/// The shared stash, transfer.gst for softcore and transfer.gsh for hardcore characters.
 pub struct Stash {
-    pub tabs: Vec<Vec<InventoryItem>>,
+    initial_key: u32,
+    pub mod_name: String,
+    /// Expansion flags rather than a bool, written back as read
+    pub expansion: u8,
+    pub tabs: Vec<StashTab>,
 }
 
 impl Stash {
-    pub fn new(path: &PathBuf) -> Result<Self, Error> {
-        let mut decrypt = Decrypt::new(path)?;
-        let val = decrypt.read_int();
-        assert_eq!(val, 2);
-        let (block_pos, block) = decrypt.read_block_start();
-        assert_eq!(block_pos, 18);
-        let stash_version = decrypt.read_int();
-        assert_eq!(stash_version, 5); // Stash file version 5
-        assert_eq!(decrypt.next_int(), 0);
-        let _str_mod = decrypt.read_str()?;
-
-        if stash_version >= 5 {
-            let _has_expansion1 = decrypt.read_bool(); // does this refer to AoM?
-        }
+    pub fn new(path: &PathBuf) -> Result<Self, GdError> {
+        Self::read(Decrypt::new(path)?)
+    }
+
+    /// Read a stash file that is already in memory.
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<Self, GdError> {
+        Self::read(Decrypt::from_bytes(bytes)?)
+    }
+
+    fn read(mut decrypt: Decrypt) -> Result<Self, GdError> {
+        decrypt.expect_int("stash file magic", 2)?;
+        let block = decrypt.read_block_start(18)?;
+        decrypt.expect_int("stash file version", STASH_VERSION)?;
+        decrypt.expect_next_int("stash header terminator", 0)?;
+        let mod_name = decrypt.read_str()?;
+        let expansion = decrypt.read_byte()?;
 
-        let tabs_count = decrypt.read_int();
+        let tabs_count = decrypt.read_int()?;
         let mut tabs = Vec::new();
 
         for _ in 0..tabs_count {
-            tabs.push(read_stash_tab(&mut decrypt)?);
+            tabs.push(StashTab::read(&mut decrypt)?);
         }
-        decrypt.read_block_end(&block).unwrap();
+        decrypt.read_block_end(&block)?;
 
-        Ok(Self { tabs })
+        Ok(Self {
+            initial_key: decrypt.initial_key(),
+            mod_name,
+            expansion,
+            tabs,
+        })
     }
-}
 
-pub fn read_stash_tab(decrypt: &mut Decrypt) -> Result<Vec<InventoryItem>, Error> {
-    let mut items = Vec::new();
-    let (_block_start, tab_block) = decrypt.read_block_start();
-    let _stash_width = decrypt.read_int();
-    let _stash_height = decrypt.read_int();
-    let item_count = decrypt.read_int();
+    /// Encrypt the stash as the game would. An unchanged stash comes out byte for byte as it was read.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut encrypt = Encrypt::new(self.initial_key);
+        encrypt.write_int(2);
+        let block = encrypt.write_block_start(18);
+        encrypt.write_int(STASH_VERSION);
+        encrypt.write_next_int(0);
+        encrypt.write_str(&self.mod_name);
+        encrypt.write_byte(self.expansion);
+        encrypt.write_int(self.tabs.len() as u32);
+        for tab in &self.tabs {
+            tab.write(&mut encrypt);
+        }
+        encrypt.write_block_end(block);
+        encrypt.into_bytes()
+    }
+
+    /// Write the stash file, replacing any old one.
+    pub fn write(&self, path: &Path) -> Result<(), GdError> {
+        encrypt::replace_file(path, &self.to_bytes())
+    }
 
-    for _ in 0..item_count {
-        let si = StashItem::read(decrypt)?;
-        items.push(si.item);
+    pub fn items(&self) -> impl Iterator<Item = &InventoryItem> {
+        self.tabs.iter().flat_map(StashTab::items)
     }
-    decrypt.read_block_end(&tab_block).unwrap();
-    Ok(items)
 }
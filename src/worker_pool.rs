@@ -0,0 +1,66 @@
+use std::panic;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::thread;
+
+/// Runs jobs on a fixed number of threads. Everything that is loaded in parallel goes through the same pool, so the
+/// number of threads doesn't grow with the number of files or records.
+#[derive(Clone, Copy, Debug)]
+pub struct WorkerPool {
+    threads: usize,
+}
+
+impl Default for WorkerPool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl WorkerPool {
+    /// One thread per CPU.
+    pub fn new() -> Self {
+        Self::with_threads(thread::available_parallelism().map_or(1, |n| n.get()))
+    }
+
+    pub fn with_threads(threads: usize) -> Self {
+        Self { threads: threads.max(1) }
+    }
+
+    pub fn threads(&self) -> usize {
+        self.threads
+    }
+
+    /// Call "f" on every input and return the results in the same order as the inputs. The workers take the next
+    /// input as soon as they are done with the previous one, so a few slow inputs don't hold up the rest.
+    pub fn map<T: Sync, R: Send>(&self, inputs: &[T], f: impl Fn(&T) -> R + Sync) -> Vec<R> {
+        let threads = self.threads.min(inputs.len());
+        if threads <= 1 {
+            return inputs.iter().map(f).collect();
+        }
+
+        let next = AtomicUsize::new(0);
+        let mut results: Vec<(usize, R)> = thread::scope(|scope| {
+            let workers: Vec<_> = (0..threads)
+                .map(|_| {
+                    scope.spawn(|| {
+                        let mut results = Vec::new();
+                        loop {
+                            let i = next.fetch_add(1, Ordering::Relaxed);
+                            let Some(input) = inputs.get(i) else {
+                                break;
+                            };
+                            results.push((i, f(input)));
+                        }
+                        results
+                    })
+                })
+                .collect();
+            workers
+                .into_iter()
+                .flat_map(|worker| worker.join().unwrap_or_else(|e| panic::resume_unwind(e)))
+                .collect()
+        });
+        results.sort_unstable_by_key(|(i, _)| *i);
+        results.into_iter().map(|(_, result)| result).collect()
+    }
+}
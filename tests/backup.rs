@@ -0,0 +1,38 @@
+use gdlc::backup::BackupFile;
+use gdlc::backup::Backups;
+
+use std::fs;
+use std::path::PathBuf;
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("gdlc-test-{name}-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn restore_oldest_backup_at_the_limit() {
+    let dir = temp_dir("restore-oldest");
+    let save_dir = dir.join("save");
+    let save_path = save_dir.join("main/_Alina/player.gdc");
+    fs::create_dir_all(save_path.parent().unwrap()).unwrap();
+    fs::write(&save_path, "first").unwrap();
+    let backups = Backups::new(dir.join("backups"), save_dir, 2);
+
+    let mut oldest = backups.create("move 1", &[&save_path]).unwrap();
+    oldest.write(&save_path, b"second").unwrap();
+    let mut newest = backups.create("move 2", &[&save_path]).unwrap();
+    newest.write(&save_path, b"third").unwrap();
+    assert_eq!(backups.list().unwrap().len(), 2);
+
+    // Backing up "third" before restoring rotates the oldest snapshot away
+    let files: Vec<&BackupFile> = oldest.files.iter().collect();
+    let undo = backups.restore(&oldest, &files).unwrap();
+    assert_eq!(fs::read(&save_path).unwrap(), b"first");
+
+    let undo_files: Vec<&BackupFile> = undo.files.iter().collect();
+    backups.restore(&undo, &undo_files).unwrap();
+    assert_eq!(fs::read(&save_path).unwrap(), b"third");
+    fs::remove_dir_all(&dir).unwrap();
+}
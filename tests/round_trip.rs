@@ -0,0 +1,61 @@
+use gdlc::decrypt::Decrypt;
+use gdlc::encrypt::Encrypt;
+use gdlc::player::CharacterItems;
+use gdlc::stash::Stash;
+
+use std::fs;
+use std::path::PathBuf;
+
+fn read_sample(name: &str) -> Vec<u8> {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(name);
+    fs::read(&path).unwrap_or_else(|e| panic!("{}: {e}", path.display()))
+}
+
+#[test]
+fn primitives_round_trip() {
+    let mut encrypt = Encrypt::new(0x12345678);
+    encrypt.write_int(42);
+    let block = encrypt.write_block_start(7);
+    encrypt.write_byte(0xff);
+    encrypt.write_bool(true);
+    encrypt.write_float(1.5);
+    encrypt.write_str("Cronley's Gang");
+    encrypt.write_wide_string("Ülrich");
+    encrypt.write_block_end(block);
+
+    let mut decrypt = Decrypt::from_bytes(encrypt.into_bytes()).unwrap();
+    assert_eq!(decrypt.read_int().unwrap(), 42);
+    let block = decrypt.read_block_start(7).unwrap();
+    assert_eq!(decrypt.read_byte().unwrap(), 0xff);
+    assert!(decrypt.read_bool().unwrap());
+    assert_eq!(decrypt.read_float().unwrap(), 1.5);
+    assert_eq!(decrypt.read_str().unwrap(), "Cronley's Gang");
+    assert_eq!(decrypt.read_wide_string().unwrap(), "Ülrich");
+    decrypt.read_block_end(&block).unwrap();
+    assert_eq!(decrypt.remaining(), 0);
+}
+
+#[test]
+fn character_round_trip() {
+    let bytes = read_sample("player.gdc");
+    let character = CharacterItems::from_bytes(bytes.clone()).unwrap();
+    assert!(character.to_bytes() == bytes, "player.gdc changed after writing it back");
+}
+
+#[test]
+fn stash_round_trip() {
+    let bytes = read_sample("transfer.gst");
+    let stash = Stash::from_bytes(bytes.clone()).unwrap();
+    assert!(stash.to_bytes() == bytes, "transfer.gst changed after writing it back");
+}
+
+#[test]
+fn stash_write() {
+    let bytes = read_sample("transfer.gst");
+    let stash = Stash::from_bytes(bytes.clone()).unwrap();
+    let path = std::env::temp_dir().join(format!("gdlc-test-{}.gst", std::process::id()));
+    stash.write(&path).unwrap();
+    let written = fs::read(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert!(written == bytes, "written transfer.gst differs from the original");
+}